@@ -1,5 +1,6 @@
 use std::any::{Any, TypeId};
 use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 pub trait KAny: Any + Send + Sync {
     fn tid(&self) -> u64;
@@ -45,6 +46,14 @@ impl dyn KAny {
         }
     }
 
+    pub fn downcast_arc<T: KAny>(self: Arc<Self>) -> Option<Arc<T>> {
+        if self.is::<T>() {
+            unsafe { Some(Arc::from_raw(Arc::into_raw(self) as *const T)) }
+        } else {
+            None
+        }
+    }
+
     /// # Safety
     /// the target type must match the origin type
     pub unsafe fn downcast_unchecked<T: KAny>(self: Box<Self>) -> Box<T> {
@@ -8,7 +8,7 @@ async fn main() {
     let cortex = Cortex::new(Arc::new(()));
     let _scope = cortex.plug(async move |cortex: Arc<Cortex>| {
         println!("Hello World");
-        cortex.scope.dispose();
+        cortex.root.upgrade().unwrap().scope.dispose();
         Ok(())
     }, ()).expect("failed to `Cortex::plug` the plugin");
     cortex.run().await
@@ -1,32 +1,43 @@
 use crate::any::KAny;
 use crate::cat::Cat;
-use crate::events::Handler;
+use crate::events::{
+    BuiltinEvent, EventHandler, Filter, InternalEvent, EventMatcher, EventMessage, EventNya, EventResult, Handler, HandlerTrait, Handlers, Listener,
+    ListenerHandle, ListenerOptions, OnceListener, Registered, ToTrigger,
+};
 use crate::plugin::Plugin;
 use crate::pnp::Pluggable;
 use crate::registry::Registry;
 use crate::result;
 use crate::result::CrowdError;
-use crate::tasker::{Task, Tasker};
-use crate::utils::lazy::LazyUpdate;
+use crate::service::{ServiceChange, ServiceRef};
+use crate::tasker::{PoolConfig, Priority, Task, TaskHandle, TaskInfo, Tasker, WorkerPool};
+use crate::timer::{Debounced, Throttled};
+use crate::utils::lazy::{LazyState, LazyUpdate};
 use crate::utils::LateInit;
 use actix::{Actor, Addr, Message};
 use core::fmt;
 use crossbeam::atomic::AtomicCell;
-use dashmap::{DashMap, DashSet};
+use dashmap::DashSet;
+use futures::future::Either;
 use futures::{Future, FutureExt};
+use futures::future::BoxFuture;
+use tokio::task::JoinHandle;
 use std::cell::UnsafeCell;
+use std::collections::HashMap;
 use std::fmt::Formatter;
 use std::future::IntoFuture;
 use std::hash::{Hash, Hasher};
-use std::mem::MaybeUninit;
 use std::ops::Deref;
-use std::panic::UnwindSafe;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::panic::{AssertUnwindSafe, UnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 use std::{mem, ptr};
 use tokio::sync as concurrent;
+use tokio_util::sync::CancellationToken;
 
-const WORKER_COUNT: u8 = 1;
+const DISPOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum ScopeState {
@@ -42,6 +53,11 @@ pub struct MainScope {
     context: Weak<Cortex>,
     plugin: Option<Arc<dyn Plugin>>,
     children: DashSet<Arc<Scope>>,
+    disposed: AtomicBool,
+    /// The plugin-level cleanups, run after every fork is disposed.
+    disposables: Mutex<Vec<Disposable>>,
+    /// Resolves to the problems of the plugin-level cleanups once they are finished, set when the runtime is disposed.
+    cleaning: Mutex<Option<BoxFuture<'static, Vec<String>>>>,
     pub alone: bool,
 }
 
@@ -78,15 +94,18 @@ impl Eq for MainScope {}
 
 impl MainScope {
     pub(crate) fn new(
-        context: Arc<Cortex>,
+        registry: &Registry,
         plugin: Option<impl Plugin + 'static>,
     ) -> Arc<MainScope> {
         Arc::new(MainScope {
-            id: AtomicCell::new(Some(context.registry.counter.fetch())),
+            id: AtomicCell::new(Some(registry.counter.fetch())),
             name: Some(Arc::from("root")),
-            context: Arc::downgrade(&context),
+            context: registry.context.clone(),
             plugin: plugin.map(|plugin| Arc::new(plugin) as Arc<dyn Plugin + 'static>),
             children: DashSet::new(),
+            disposed: AtomicBool::new(false),
+            disposables: Mutex::new(vec![]),
+            cleaning: Mutex::new(None),
             alone: true,
         })
     }
@@ -96,7 +115,25 @@ impl MainScope {
         parent: Arc<Cortex>,
         config: Arc<impl KAny + ?Sized>,
     ) -> Arc<Scope> {
-        Scope::new(self.clone(), parent, config, WORKER_COUNT)
+        let token = parent.scope.lifecycle.token.child_token();
+        // the fork has its own context, so that the plugin binds its listeners, timers and services to the fork
+        let ctx = parent.derive(
+            |weak| {
+                Scope::new(
+                    self,
+                    weak,
+                    &parent.registry,
+                    config,
+                    token,
+                    Arc::downgrade(&parent.scope),
+                )
+            },
+            parent.isolated.clone(),
+            parent.filter.clone(),
+        );
+        ctx.scope.own(ctx.clone());
+        Scope::attach(&ctx.scope);
+        ctx.scope.clone()
     }
 
     #[deprecated(note = "this method is deprecated, please use `MainScope::id()` instead")]
@@ -112,18 +149,91 @@ impl MainScope {
         self.plugin.as_ref().map(AsRef::as_ref)
     }
 
+    pub fn children(&self) -> Vec<Arc<Scope>> {
+        self.children.iter().map(|scope| scope.clone()).collect()
+    }
+
+    /// Registers a plugin-level cleanup, which runs when the runtime is disposed.
+    pub fn collect(&self, name: impl Into<Arc<str>>, dispose: impl FnOnce() + Send + Sync + 'static) {
+        self.push_cleanup(name.into(), Cleanup::Sync(Box::new(dispose)));
+    }
+
+    /// Registers an asynchronous plugin-level cleanup, which is awaited in the background when the runtime is disposed,
+    /// or by [`Cortex::shutdown`].
+    pub fn collect_async<F, Fut>(&self, name: impl Into<Arc<str>>, dispose: F)
+    where
+        F: FnOnce() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = color_eyre::Result<()>> + Send + Sync + 'static,
+    {
+        let dispose = Cleanup::Async(Box::new(move || Box::pin(dispose()) as CleanupFuture));
+        self.push_cleanup(name.into(), dispose);
+    }
+
+    fn push_cleanup(&self, name: Arc<str>, dispose: Cleanup) {
+        let id = self.context.upgrade().map_or(0, |ctx| ctx.registry.counter.fetch());
+        self.disposables.lock().unwrap().push(Disposable { id, name, dispose });
+    }
+
+    /// Disposes every fork of the plugin, unloads the plugin from the registry,
+    /// then runs the plugin-level cleanups in the reverse order of their registration.
+    ///
+    /// `dispose` is emitted by each fork, not by the runtime itself.
     pub fn dispose(self: Arc<MainScope>) {
+        if self.disposed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        for scope in self.children() {
+            scope.dispose();
+        }
+        let ctx = self.context.upgrade();
+        if let (Some(ctx), Some(plugin)) = (&ctx, &self.plugin) {
+            ctx.registry
+                .entries
+                .remove_if(&plugin.identifier(), |_, rt| ptr::eq(rt.as_ptr(), Arc::as_ptr(&self)));
+        }
+        let mut disposables = mem::take(&mut *self.disposables.lock().unwrap());
+        disposables.reverse();
+        let context = self.context.clone();
+        let (mut problems, pending) = run_cleanups(disposables, DISPOSE_TIMEOUT, move |(problem, evt)| {
+            if let Some(ctx) = context.upgrade() {
+                ctx.emit_internal(BuiltinEvent::Internal(evt(problem)));
+            }
+        });
+        *self.cleaning.lock().unwrap() = Some(Box::pin(async move {
+            if let Some(pending) = pending {
+                problems.extend(pending.await.unwrap_or_default());
+            }
+            problems
+        }));
         self.id.store(None)
-        // TODO: reset
-        // TODO: serial event
+    }
+
+    /// Waits until the plugin-level cleanups started by [`MainScope::dispose`] are finished,
+    /// returns the problems they reported.
+    ///
+    /// Returns nothing if the runtime is not disposed, or if they have already been awaited.
+    pub(crate) async fn cleaned(&self) -> Vec<String> {
+        let cleaning = self.cleaning.lock().unwrap().take();
+        match cleaning {
+            Some(cleaning) => cleaning.await,
+            None => vec![],
+        }
     }
 }
 pub struct Scope {
     lifecycle: Arc<Lifecycle>,
     runtime: Arc<MainScope>,
     context: Weak<Cortex>,
+    /// Keeps `context` alive until the scope is disposed, unless it is the root context.
+    owned: Mutex<Option<Arc<Cortex>>>,
+    /// The cleanup of the parent scope disposing this scope, forgotten once this scope is disposed on its own.
+    collected: Mutex<Option<DisposeHandle>>,
+    /// The scope this scope is disposed with, empty for the root scope.
+    parent: Weak<Scope>,
     config: Arc<dyn KAny>,
-    handlers: Arc<DashMap<usize, Arc<dyn Handler>>>,
+    pub(crate) handlers: Arc<Handlers>,
+    /// The service slots read through a [`ServiceRef`], so that replacing them does not restart the scope.
+    swappable: DashSet<Arc<str>>,
 }
 
 impl Hash for MainScope {
@@ -153,7 +263,7 @@ impl fmt::Debug for Scope {
 pub struct LifeStatus {
     mutex: Mutex<()>,
     is_active: UnsafeCell<bool>,
-    error: UnsafeCell<Option<result::Error>>,
+    error: UnsafeCell<Option<Arc<result::Error>>>,
 }
 
 unsafe impl Send for LifeStatus {}
@@ -162,7 +272,7 @@ unsafe impl Sync for LifeStatus {}
 impl LifeStatus {
     fn has_error(&self) -> bool {
         let _guard = self.mutex.lock();
-        unsafe { self.error.get().as_ref() }.is_some()
+        unsafe { &*self.error.get() }.is_some()
     }
 
     fn is_active(&self) -> bool {
@@ -170,22 +280,178 @@ impl LifeStatus {
         unsafe { self.is_active.get().read() }
     }
 
+    fn error(&self) -> Option<Arc<result::Error>> {
+        let _guard = self.mutex.lock();
+        unsafe { &*self.error.get() }.clone()
+    }
+
     fn set_error(&self, err: result::Error) {
         let _guard = self.mutex.lock();
         unsafe {
-            self.error.get().write(Some(err));
+            *self.error.get() = Some(Arc::new(err));
+        }
+    }
+
+    fn clear_error(&self) {
+        let _guard = self.mutex.lock();
+        unsafe {
+            *self.error.get() = None;
+        }
+    }
+
+    fn set_active(&self, active: bool) {
+        let _guard = self.mutex.lock();
+        unsafe {
+            self.is_active.get().write(active);
         }
     }
 }
 
 pub struct Lifecycle {
     uid: AtomicCell<Option<usize>>,
+    scope: Weak<Scope>,
     state: LazyUpdate<ScopeState>,
     disposed: AtomicBool,
     notifier: concurrent::Notify,
     tasker: Tasker,
     status: LifeStatus,
-    disposables: Vec<Box<dyn FnOnce() + Send + Sync>>,
+    disposables: Mutex<Vec<Disposable>>,
+    dispose_timeout: AtomicCell<Duration>,
+    /// The number of consecutive failures of the plugin.
+    failures: AtomicU32,
+    /// Increased on every reset, so that a pending restart is dropped once the scope is restarted otherwise.
+    generation: AtomicUsize,
+    /// Cancelled when the scope is disposed, derived from the token of the parent scope.
+    token: CancellationToken,
+    /// Resolves once the last `apply` of the plugin has returned or is dropped.
+    applying: Mutex<Option<concurrent::oneshot::Receiver<()>>>,
+}
+
+/// The future of an asynchronous cleanup registered by [`Cortex::collect_async`].
+type CleanupFuture = Pin<Box<dyn Future<Output = color_eyre::Result<()>> + Send + Sync>>;
+
+/// The description of a failed cleanup, with the internal event reporting it.
+type Problem = (String, fn(String) -> InternalEvent);
+
+pub(crate) enum Cleanup {
+    Sync(Box<dyn FnOnce() + Send + Sync>),
+    Async(Box<dyn FnOnce() -> CleanupFuture + Send + Sync>),
+}
+
+struct Disposable {
+    id: usize,
+    name: Arc<str>,
+    dispose: Cleanup,
+}
+
+/// Runs a synchronous cleanup, returns the problem to report if it panics.
+fn run_sync(name: &str, dispose: Box<dyn FnOnce() + Send + Sync>) -> Option<Problem> {
+    if std::panic::catch_unwind(AssertUnwindSafe(dispose)).is_ok() {
+        return None;
+    }
+    Some((format!("panic when disposing `{name}`"), InternalEvent::Error))
+}
+
+/// Runs a cleanup, asynchronous cleanups are awaited within `timeout`.
+///
+/// Returns the problem to report if the cleanup fails, panics or times out.
+async fn run_cleanup(disposable: Disposable, timeout: Duration) -> Option<Problem> {
+    let (name, dispose) = match disposable.dispose {
+        Cleanup::Async(dispose) => (disposable.name, dispose),
+        Cleanup::Sync(dispose) => return run_sync(&disposable.name, dispose),
+    };
+    let fut = AssertUnwindSafe(dispose()).catch_unwind();
+    match tokio::time::timeout(timeout, fut).await {
+        Ok(Ok(Ok(()))) => None,
+        Ok(Ok(Err(err))) => Some((format!("failed to dispose `{name}`: {err}"), InternalEvent::Error)),
+        Ok(Err(_)) => Some((format!("panic when disposing `{name}`"), InternalEvent::Error)),
+        Err(_) => Some((
+            format!("disposing `{name}` timed out after {timeout:?}"),
+            InternalEvent::Warn,
+        )),
+    }
+}
+
+/// Runs the cleanups in order, passing their problems to `report`.
+///
+/// Synchronous cleanups run immediately, so that the tasks, services and forks of a scope are gone once it is disposed,
+/// asynchronous ones are awaited in the background, one by one.
+///
+/// Returns the problems of the synchronous cleanups, and the task resolving to the problems of the asynchronous ones.
+fn run_cleanups(
+    disposables: Vec<Disposable>,
+    timeout: Duration,
+    report: impl Fn(Problem) + Send + 'static,
+) -> (Vec<String>, Option<JoinHandle<Vec<String>>>) {
+    let mut problems = vec![];
+    let mut pending = vec![];
+    for disposable in disposables {
+        match disposable.dispose {
+            Cleanup::Sync(dispose) => {
+                if let Some((problem, evt)) = run_sync(&disposable.name, dispose) {
+                    problems.push(problem.clone());
+                    report((problem, evt));
+                }
+            }
+            Cleanup::Async(_) => pending.push(disposable),
+        }
+    }
+    if pending.is_empty() {
+        return (problems, None);
+    }
+    let pending = tokio::task::spawn(async move {
+        let mut problems = vec![];
+        for disposable in pending {
+            if let Some((problem, evt)) = run_cleanup(disposable, timeout).await {
+                problems.push(problem.clone());
+                report((problem, evt));
+            }
+        }
+        problems
+    });
+    (problems, Some(pending))
+}
+
+/// A handle to a cleanup registered by [`Cortex::effect`] or [`Cortex::collect`].
+///
+/// The cleanup runs when the scope is disposed, or earlier through [`DisposeHandle::dispose`].
+#[derive(Clone)]
+pub struct DisposeHandle {
+    id: usize,
+    lifecycle: Weak<Lifecycle>,
+}
+
+impl DisposeHandle {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Runs the cleanup now, returns `false` if it has already been run.
+    pub fn dispose(&self) -> bool {
+        let Some(lifecycle) = self.lifecycle.upgrade() else {
+            return false;
+        };
+        let disposable = {
+            let mut disposables = lifecycle.disposables.lock().unwrap();
+            match disposables.iter().position(|disposable| disposable.id == self.id) {
+                None => return false,
+                Some(index) => disposables.remove(index),
+            }
+        };
+        lifecycle.run_disposable(disposable);
+        true
+    }
+
+    /// Removes the cleanup without running it, returns `false` if it has already been run.
+    pub(crate) fn forget(&self) -> bool {
+        let Some(lifecycle) = self.lifecycle.upgrade() else {
+            return false;
+        };
+        let mut disposables = lifecycle.disposables.lock().unwrap();
+        let len = disposables.len();
+        disposables.retain(|disposable| disposable.id != self.id);
+        disposables.len() != len
+    }
 }
 
 impl fmt::Debug for Lifecycle {
@@ -211,11 +477,17 @@ impl fmt::Debug for Lifecycle {
 }
 
 impl Lifecycle {
-    pub(crate) fn new(uid: usize, worker_count: u8) -> Arc<Self> {
+    pub(crate) fn new(
+        uid: usize,
+        scope: Weak<Scope>,
+        token: CancellationToken,
+        pool: Arc<WorkerPool>,
+    ) -> Arc<Self> {
         Arc::new_cyclic(|weak: &Weak<Lifecycle>| {
             let weak = weak.clone();
             Self {
                 uid: AtomicCell::new(Some(uid)),
+                scope,
                 state: LazyUpdate::new(move |_prev| {
                     let this = weak.upgrade().unwrap();
                     if this.uid.load().is_none() {
@@ -231,13 +503,18 @@ impl Lifecycle {
                 }),
                 disposed: Default::default(),
                 notifier: Default::default(),
-                tasker: Tasker::new(worker_count),
+                tasker: Tasker::new(uid, pool),
                 status: LifeStatus {
                     mutex: Mutex::new(()),
                     is_active: UnsafeCell::new(false),
                     error: UnsafeCell::new(None),
                 },
-                disposables: vec![],
+                disposables: Mutex::new(vec![]),
+                dispose_timeout: AtomicCell::new(DISPOSE_TIMEOUT),
+                failures: AtomicU32::new(0),
+                generation: AtomicUsize::new(0),
+                token,
+                applying: Mutex::new(None),
             }
         })
     }
@@ -245,7 +522,9 @@ impl Lifecycle {
     pub(crate) fn notify_dispose(&self) {
         self.uid.store(None);
         self.disposed.store(true, Ordering::SeqCst);
-        self.state.update();
+        self.status.set_active(false);
+        self.token.cancel();
+        self.update_state();
         self.notifier.notify_waiters();
         self.tasker.dispose();
     }
@@ -254,9 +533,159 @@ impl Lifecycle {
         self.uid.load()
     }
 
+    /// Resolves once `done` holds, which is checked again whenever the state changes.
+    async fn wait(&self, done: impl Fn(&Self) -> bool) {
+        loop {
+            let notified = self.notifier.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if done(self) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Resolves once the scope is disposed.
+    pub(crate) async fn disposed(&self) {
+        self.wait(|this| this.disposed.load(Ordering::SeqCst)).await
+    }
+
     pub(crate) fn set_error(&self, err: result::Error) {
         self.status.set_error(err);
     }
+
+    /// Removes a cleanup without running it, once the resource it releases is gone.
+    fn forget(&self, id: usize) {
+        self.disposables
+            .lock()
+            .unwrap()
+            .retain(|disposable| disposable.id != id);
+    }
+
+    /// Marks the scope `Failed` after a panic in a task bound to it.
+    pub(crate) fn fail(&self, name: &str) {
+        self.set_error(result::Error::PnpPanic(format!("panic in `{name}`")));
+        self.update_state();
+    }
+
+    pub(crate) fn collect(self: &Arc<Self>, id: usize, name: Arc<str>, dispose: Cleanup) -> DisposeHandle {
+        self.disposables
+            .lock()
+            .unwrap()
+            .push(Disposable { id, name, dispose });
+        DisposeHandle {
+            id,
+            lifecycle: Arc::downgrade(self),
+        }
+    }
+
+    fn report(&self, evt: InternalEvent) {
+        if let Some(scope) = self.scope.upgrade() {
+            scope.emit_internal(evt);
+        }
+    }
+
+    /// Reports the problem of a cleanup, if any, and returns its description.
+    fn reported(&self, problem: Option<Problem>) -> Option<String> {
+        let (problem, evt) = problem?;
+        self.report(evt(problem.clone()));
+        Some(problem)
+    }
+
+    /// Runs a cleanup, asynchronous cleanups are awaited in the background.
+    fn run_disposable(self: &Arc<Self>, disposable: Disposable) {
+        self.run_cleanups(vec![disposable]);
+    }
+
+    fn take_disposables(&self) -> Vec<Disposable> {
+        let mut disposables = mem::take(&mut *self.disposables.lock().unwrap());
+        disposables.reverse();
+        disposables
+    }
+
+    fn run_cleanups(self: &Arc<Self>, disposables: Vec<Disposable>) {
+        let this = self.clone();
+        run_cleanups(disposables, self.dispose_timeout.load(), move |problem| {
+            this.reported(Some(problem));
+        });
+    }
+
+    /// Runs every cleanup, in the reverse order of their registration, see [`run_cleanups`].
+    pub(crate) fn run_disposables(self: &Arc<Self>) {
+        self.run_cleanups(self.take_disposables());
+    }
+
+    /// Runs every cleanup in the reverse order of their registration, awaiting the asynchronous ones
+    /// once the synchronous ones have run.
+    ///
+    /// Returns the problems reported by the cleanups.
+    pub(crate) async fn run_disposables_async(self: &Arc<Self>) -> Vec<String> {
+        let (pending, disposables): (Vec<_>, Vec<_>) = self
+            .take_disposables()
+            .into_iter()
+            .partition(|disposable| matches!(disposable.dispose, Cleanup::Async(_)));
+        let mut problems = vec![];
+        for disposable in disposables.into_iter().chain(pending) {
+            let problem = run_cleanup(disposable, self.dispose_timeout.load()).await;
+            problems.extend(self.reported(problem));
+        }
+        problems
+    }
+
+    /// Schedules a restart according to the restart policy of the plugin, once its `apply` has returned.
+    ///
+    /// Nothing is scheduled if the scope has been reset since `generation`.
+    fn schedule_restart(&self, generation: usize) {
+        if self.generation.load(Ordering::SeqCst) != generation || self.token.is_cancelled() {
+            return;
+        }
+        let Some(scope) = self.scope.upgrade() else {
+            return;
+        };
+        let Some(plugin) = scope.runtime.plugin.as_ref() else {
+            return;
+        };
+        let failed = self.status.has_error();
+        let failures = match failed {
+            true => self.failures.fetch_add(1, Ordering::SeqCst) + 1,
+            false => {
+                self.failures.store(0, Ordering::SeqCst);
+                0
+            }
+        };
+        let Some(delay) = plugin.restart().next(failed, failures) else {
+            return;
+        };
+        scope.emit_internal(InternalEvent::Restart(scope.clone(), failures));
+        let scope = Arc::downgrade(&scope);
+        tokio::task::spawn(async move {
+            tokio::time::sleep(delay).await;
+            match scope.upgrade() {
+                Some(scope) if scope.lifecycle.generation.load(Ordering::SeqCst) == generation => {
+                    scope.restart()
+                }
+                _ => {}
+            }
+        });
+    }
+
+    /// Recomputes the state, and emits `internal/state` if it has changed.
+    pub(crate) fn update_state(&self) {
+        let prev = match self.state.state(Ordering::Acquire) {
+            LazyState::Initialized => Some(self.state.get()),
+            _ => None,
+        };
+        self.state.update();
+        let state = self.state.get();
+        if prev == Some(state) {
+            return;
+        }
+        self.notifier.notify_waiters();
+        if let Some(scope) = self.scope.upgrade() {
+            scope.emit_internal(InternalEvent::State(scope.clone(), state));
+        }
+    }
 }
 
 impl Hash for Scope {
@@ -278,32 +707,55 @@ impl PartialEq for Scope {
 impl Eq for Scope {}
 
 impl Scope {
+    /// Creates a scope bound to `context`, which may still be under construction.
+    ///
+    /// The scope is not started until it is [attached](Scope::attach).
     pub(crate) fn new<T: Send + Sync + ?Sized + 'static>(
         runtime: Arc<MainScope>,
-        context: Arc<Cortex>,
+        context: &Weak<Cortex>,
+        registry: &Registry,
         config: Arc<T>,
-        worker_count: u8,
+        token: CancellationToken,
+        parent: Weak<Scope>,
     ) -> Arc<Self> {
-        let id = context.registry.counter.fetch();
-        let mut this = Arc::new(Self {
-            context: Arc::downgrade(&context),
+        let id = registry.counter.fetch();
+        let pool = registry.pool.clone();
+        Arc::new_cyclic(|weak| Self {
+            context: context.clone(),
+            owned: Mutex::new(None),
+            collected: Mutex::new(None),
+            parent,
             config: Arc::new(config),
-            runtime: runtime.clone(),
+            runtime,
             handlers: Default::default(),
-            lifecycle: Lifecycle::new(id, worker_count),
-        });
+            swappable: DashSet::new(),
+            lifecycle: Lifecycle::new(id, weak.clone(), token, pool),
+        })
+    }
 
-        Scope::init(Arc::get_mut(&mut this).unwrap());
+    /// Keeps `context` alive until the scope is disposed.
+    fn own(&self, context: Arc<Cortex>) {
+        *self.owned.lock().unwrap() = Some(context);
+    }
+
+    /// Adds the scope to its runtime, and applies the plugin once it is ready.
+    pub(crate) fn attach(this: &Arc<Self>) {
         // TODO: this.dispose = ...
-        runtime.children.insert(this.clone());
+        this.runtime.children.insert(this.clone());
         // TODO: add dispose to runtime.disposables
-        // TODO: emit event
-        this
+        this.emit_internal(InternalEvent::Fork(this.clone()));
+        if let Some(context) = this.ctx() {
+            this.emit(BuiltinEvent::Fork(context, this.config.clone()));
+        }
+        Scope::init(this);
     }
 
-    pub fn start(this: &mut Self) {
-        // if (!this.ready || this.isActive || this.uid === null) return true
-        // this.isActive = true
+    pub fn start(this: &Self) {
+        if !this.ready() || this.lifecycle.status.is_active() || this.id().is_none() {
+            return;
+        }
+        this.lifecycle.status.set_active(true);
+        this.lifecycle.update_state();
         // this.updateStatus(() => this.hasError = false)
 
         if this.runtime.plugin.is_none() {
@@ -311,8 +763,23 @@ impl Scope {
         }
 
         let rt = this.runtime.clone();
-        let cortex = this.context.upgrade().unwrap();
-        this.ensure(move || {
+        let Some(cortex) = this.ctx() else {
+            return;
+        };
+        let ready = cortex.clone();
+        let scope = this.lifecycle.scope.clone();
+        // `apply` is expected to return once `Cortex::cancelled` resolves,
+        // otherwise it is dropped when the dispose timeout has elapsed since the scope is disposed
+        let lifecycle = this.lifecycle.clone();
+        let expired = Box::pin(async move {
+            lifecycle.token.cancelled().await;
+            tokio::time::sleep(lifecycle.dispose_timeout.load()).await;
+        });
+        let token = this.lifecycle.token.clone();
+        // the previous `apply` is cancelled by `Scope::reset`, wait until it is dropped before applying again
+        let (done, applied) = concurrent::oneshot::channel::<()>();
+        let previous = this.lifecycle.applying.lock().unwrap().replace(applied);
+        let scheduled = this.schedule("apply", Priority::High, move || {
             // freak I do the freaking unsafe magic to make it unwind safe
             cve_rs::transmute::<
                 Box<dyn Future<Output = Result<(), result::Error>> + Send + Unpin>,
@@ -323,14 +790,31 @@ impl Scope {
                         + UnwindSafe
                         + Unpin,
                 >,
-            >(Box::new(
-                rt.plugin
-                    .as_ref()
-                    .unwrap()
-                    .apply(cortex)
-                    .map(|r| r.map_err(result::Error::Other)),
-            ))
-        });
+            >(Box::new(Box::pin(async move {
+                let _done = done;
+                if let Some(previous) = previous {
+                    let _ = previous.await;
+                }
+                futures::future::select(rt.plugin.as_ref().unwrap().apply(cortex), expired)
+                    .map(move |either| match either {
+                        Either::Left((r, _)) => {
+                            match scope.upgrade() {
+                                Some(scope) if r.is_ok() && !token.is_cancelled() => {
+                                    ready.emit_internal(BuiltinEvent::Ready(scope))
+                                }
+                                _ => {}
+                            }
+                            r.map_err(result::Error::Other)
+                        }
+                        Either::Right(_) => Ok(()),
+                    })
+                    .await
+            })))
+        }, true);
+        if let Err(err) = scheduled {
+            this.lifecycle.set_error(err.into());
+            this.lifecycle.update_state();
+        }
     }
 
     #[deprecated(note = "this method is deprecated, please use `Scope::id()` instead")]
@@ -338,11 +822,43 @@ impl Scope {
         self.lifecycle.id().unwrap_or(usize::MAX)
     }
 
-    fn id(&self) -> Option<usize> {
+    pub fn id(&self) -> Option<usize> {
         self.lifecycle.id()
     }
 
-    pub fn ensure<F, Fut>(&self, callback: F)
+    /// Schedules `callback` into the worker pool, an error or a panic in it marks the scope `Failed`.
+    ///
+    /// Fails with [`CrowdError::QueueFull`] if the queue of the scope is full.
+    pub fn ensure<F, Fut>(&self, callback: F) -> Result<(), CrowdError>
+    where
+        F: FnOnce() -> Fut + 'static,
+        F: Send + Sync,
+        Fut: IntoFuture<Output = result::Result<()>>,
+        <Fut as IntoFuture>::IntoFuture: Send + Sync + UnwindSafe,
+    {
+        self.ensure_task("anonymous", Priority::Normal, callback)
+    }
+
+    /// Same as [`Scope::ensure`], with the name and the priority of the scheduled task.
+    pub fn ensure_task<F, Fut>(&self, name: impl Into<Arc<str>>, priority: Priority, callback: F) -> Result<(), CrowdError>
+    where
+        F: FnOnce() -> Fut + 'static,
+        F: Send + Sync,
+        Fut: IntoFuture<Output = result::Result<()>>,
+        <Fut as IntoFuture>::IntoFuture: Send + Sync + UnwindSafe,
+    {
+        self.schedule(name, priority, callback, false)
+    }
+
+    /// Schedules a task of the scope, `restart` is only set for `apply`,
+    /// whose outcome decides whether the plugin is restarted by its [`RestartPolicy`](crate::pnp::RestartPolicy).
+    fn schedule<F, Fut>(
+        &self,
+        name: impl Into<Arc<str>>,
+        priority: Priority,
+        callback: F,
+        restart: bool,
+    ) -> Result<(), CrowdError>
     where
         F: FnOnce() -> Fut + 'static,
         F: Send + Sync,
@@ -350,28 +866,33 @@ impl Scope {
         <Fut as IntoFuture>::IntoFuture: Send + Sync + UnwindSafe,
     {
         let lifecycle = self.lifecycle.clone();
+        let generation = lifecycle.generation.load(Ordering::SeqCst);
         let wrapped = async move {
             let fut: Result<result::Result<()>, Box<dyn std::any::Any + Send>> =
                 callback().into_future().catch_unwind().await;
-            match fut {
-                Ok(Ok(())) => {}
+            let panic = match fut {
+                Ok(Ok(())) => None,
                 Ok(Err(err)) => {
                     lifecycle.set_error(err);
+                    None
                 }
                 Err(e) => {
                     lifecycle.set_error(result::Error::PnpPanic(
                         "panic when executing plugin lifecycle".to_string(),
                     ));
-                    std::panic::resume_unwind(e);
-                    // self.lifecycle.error.replace(e);
+                    Some(e)
                 }
+            };
+            lifecycle.update_state();
+            if restart {
+                lifecycle.schedule_restart(generation);
+            }
+            if let Some(e) = panic {
+                std::panic::resume_unwind(e);
             }
-            lifecycle.state.update()
         };
-        self.lifecycle.tasker.sched(Task::new_blocking(
-            Arc::from("anonymous"),
-            Box::pin(wrapped),
-        ))
+        let task = Task::new_blocking(name, Box::pin(wrapped)).priority(priority);
+        self.lifecycle.tasker.sched(task)?;
         // const task = callback()
         //     .catch((reason) => {
         //         this.context.emit(this.ctx, 'internal/error', reason)
@@ -383,12 +904,138 @@ impl Scope {
         //     })
         // this.updateStatus(() => this.tasks.add(task))
         // this.context.events._tasks.add(task)
+        Ok(())
     }
 
-    pub fn init(this: &mut Self) {
+    pub fn init(this: &Self) {
         Scope::start(this);
     }
 
+    /// Returns whether every service required by the plugin is set.
+    pub fn ready(&self) -> bool {
+        let Some(plugin) = self.runtime.plugin.as_ref() else {
+            return true;
+        };
+        let Some(ctx) = self.ctx() else {
+            return false;
+        };
+        plugin
+            .inject()
+            .required
+            .iter()
+            .all(|name| ctx.registry.services.has(&ctx.service_key(name)))
+    }
+
+    /// Returns the service slots the plugin depends on.
+    pub(crate) fn dependencies(&self) -> Vec<Arc<str>> {
+        let Some(plugin) = self.runtime.plugin.as_ref() else {
+            return vec![];
+        };
+        let Some(ctx) = self.ctx() else {
+            return vec![];
+        };
+        let inject = plugin.inject();
+        inject
+            .required
+            .iter()
+            .chain(&inject.optional)
+            .map(|name| ctx.service_key(name))
+            .collect()
+    }
+
+    /// Returns whether the plugin depends on the service slot `key`.
+    pub(crate) fn depends_on(&self, key: &str) -> bool {
+        self.dependencies().iter().any(|dep| **dep == *key)
+    }
+
+    /// Returns whether the service slot `key` is read through a [`ServiceRef`] in this scope.
+    pub(crate) fn is_swappable(&self, key: &str) -> bool {
+        self.swappable.contains(key)
+    }
+
+    pub fn state(&self) -> ScopeState {
+        self.lifecycle.state.get()
+    }
+
+    /// Returns the error of the last `apply` of the plugin, if it has failed.
+    pub fn error(&self) -> Option<Arc<result::Error>> {
+        self.lifecycle.status.error()
+    }
+
+    /// Waits until the scope reaches `state`.
+    ///
+    /// Fails with [`CrowdError::InactiveScope`] if the scope is disposed before reaching `state`.
+    pub async fn wait_until(&self, state: ScopeState) -> result::Result<()> {
+        if state == ScopeState::Disposed {
+            self.wait_disposed().await;
+            return Ok(());
+        }
+        self.lifecycle
+            .wait(|lifecycle| lifecycle.state.get() == state || lifecycle.disposed.load(Ordering::SeqCst))
+            .await;
+        match self.state() == state {
+            true => Ok(()),
+            false => Err(CrowdError::InactiveScope.into()),
+        }
+    }
+
+    /// Marks the scope `Failed` after a panic in a callback bound to it.
+    pub(crate) fn fail(&self, name: &str) {
+        self.lifecycle.fail(name)
+    }
+
+    /// Lists the pending and running tasks scheduled by [`Scope::ensure`] or spawned by [`Cortex::spawn`].
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.lifecycle.tasker.tasks()
+    }
+
+    /// Cancels every pending or running task named `name`, returns how many are cancelled.
+    ///
+    /// Cancelling `apply` marks the scope `Failed` with [`CrowdError::Cancelled`].
+    pub fn cancel_task(&self, name: &str) -> usize {
+        let cancelled = self.lifecycle.tasker.cancel(name);
+        if cancelled > 0 && name == "apply" {
+            self.lifecycle.set_error(CrowdError::Cancelled.into());
+            self.lifecycle.update_state();
+        }
+        cancelled
+    }
+
+    /// Returns the token cancelled when the scope is disposed, which is a child of the token of the parent scope.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.lifecycle.token.clone()
+    }
+
+    /// Waits until the scope is disposed.
+    pub async fn wait_disposed(&self) {
+        self.lifecycle.disposed().await
+    }
+
+    pub fn runtime(&self) -> &Arc<MainScope> {
+        &self.runtime
+    }
+
+    /// Cancels the running `apply`, runs the cleanups of the scope and puts it back to `Pending`, without disposing it.
+    fn reset(&self) {
+        self.lifecycle.generation.fetch_add(1, Ordering::SeqCst);
+        self.lifecycle.tasker.cancel("apply");
+        self.lifecycle.status.set_active(false);
+        self.lifecycle.status.clear_error();
+        self.handlers.clear();
+        self.swappable.clear();
+        self.lifecycle.run_disposables();
+        self.lifecycle.update_state();
+    }
+
+    /// Resets the scope, and applies the plugin again once it is [`ready`](Scope::ready).
+    pub fn restart(self: &Arc<Scope>) {
+        if self.id().is_none() {
+            return;
+        }
+        self.reset();
+        Scope::start(self);
+    }
+
     pub(crate) fn assert_active(&self) -> result::Result<(), CrowdError> {
         match self.id() {
             None => Err(CrowdError::InactiveScope),
@@ -396,20 +1043,164 @@ impl Scope {
         }
     }
 
-    fn ctx(&self) -> Arc<Cortex> {
-        self.context.upgrade().unwrap()
+    /// Returns the context of the scope, `None` once the scope is disposed and its context is dropped.
+    fn ctx(&self) -> Option<Arc<Cortex>> {
+        self.context.upgrade()
     }
 
-    pub fn dispose(self: &Arc<Scope>) -> bool {
+    /// Emits a built-in event on behalf of this scope.
+    ///
+    /// Once the scope is disposed and its context is dropped, the event is emitted through the root context.
+    /// Nothing is emitted while the root context is still being constructed.
+    fn emit(&self, evt: BuiltinEvent) {
+        if let Some(cortex) = self.ctx().or_else(|| self.runtime.context.upgrade()) {
+            cortex.emit_internal(evt);
+        }
+    }
+
+    fn emit_internal(&self, evt: InternalEvent) {
+        self.emit(BuiltinEvent::Internal(evt))
+    }
+
+    /// Sets how long each asynchronous cleanup of this scope may take when the scope is disposed.
+    pub fn set_dispose_timeout(&self, timeout: Duration) {
+        self.lifecycle.dispose_timeout.store(timeout);
+    }
+
+    pub fn dispose_timeout(&self) -> Duration {
+        self.lifecycle.dispose_timeout.load()
+    }
+
+    fn detach(self: &Arc<Scope>) -> bool {
+        // emitted first, so that the listeners of the scope itself are called
+        self.emit(BuiltinEvent::Dispose(self.clone()));
         let result = self.runtime.children.remove(self).is_some();
+        self.handlers.clear();
+        result
+    }
+
+    fn finish_dispose(&self) {
         self.lifecycle.notify_dispose();
-        if self.runtime.children.is_empty() && self.runtime.plugin.is_some() {
-            self.ctx()
-                .registry
-                .delete(self.runtime.plugin.as_ref().unwrap().deref());
+        if let (true, Some(plugin), Some(ctx)) =
+            (self.runtime.children.is_empty(), self.runtime.plugin.as_ref(), self.ctx())
+        {
+            ctx.registry.delete(plugin.deref());
         }
+        // the owned context refers to this scope, release it so that both can be dropped
+        drop(self.owned.lock().unwrap().take());
+        if let Some(collected) = self.collected.lock().unwrap().take() {
+            collected.forget();
+        }
+    }
+
+    fn is_disposed(&self) -> bool {
+        self.lifecycle.disposed.load(Ordering::SeqCst)
+    }
+
+    /// Disposes the scope, asynchronous cleanups keep running in the background.
+    pub fn dispose(self: &Arc<Scope>) -> bool {
+        if self.is_disposed() {
+            return false;
+        }
+        let result = self.detach();
+        self.lifecycle.run_disposables();
+        self.finish_dispose();
         result
     }
+
+    /// Disposes the scope, and waits until every cleanup is finished or timed out.
+    pub async fn dispose_async(self: &Arc<Scope>) -> bool {
+        self.dispose_reported().await.0
+    }
+
+    /// Same as [`Scope::dispose_async`], but also returns the problems reported by the cleanups.
+    async fn dispose_reported(self: &Arc<Scope>) -> (bool, Vec<String>) {
+        if self.is_disposed() {
+            return (false, vec![]);
+        }
+        let result = self.detach();
+        let problems = self.lifecycle.run_disposables_async().await;
+        self.finish_dispose();
+        (result, problems)
+    }
+
+    /// Returns the scope this scope is disposed with, `None` for the root scope.
+    fn parent(&self) -> Option<Arc<Scope>> {
+        self.parent.upgrade()
+    }
+
+    /// Returns the name of the plugin, `root` for the root scope,
+    /// or `extend` for the scopes created by [`Cortex::extend`].
+    pub fn name(&self) -> Arc<str> {
+        if let Some(plugin) = self.runtime.plugin.as_ref() {
+            return plugin.name();
+        }
+        let root = self.runtime.context.upgrade();
+        match root.is_some_and(|root| ptr::eq(Arc::as_ptr(&root.scope), self)) {
+            true => Arc::from("root"),
+            false => Arc::from("extend"),
+        }
+    }
+}
+
+/// The properties of a child context created by [`Cortex::extend`].
+#[derive(Default)]
+pub struct Overrides {
+    isolate: Vec<Arc<str>>,
+    filter: Option<Filter>,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Isolates the service `name` in the child context, see [`Cortex::isolate`].
+    pub fn isolate(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.isolate.push(name.into());
+        self
+    }
+
+    /// Restricts the events received by the child context, see [`Cortex::filtered`].
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(match self.filter {
+            Some(prev) => prev.intersect(&filter),
+            None => filter,
+        });
+        self
+    }
+}
+
+/// The outcome of disposing a scope in [`Cortex::shutdown`].
+#[derive(Clone, Debug)]
+pub struct ScopeReport {
+    pub id: usize,
+    /// The name of the scope, see [`Scope::name`].
+    pub name: Arc<str>,
+    /// The problems reported by the cleanups of the scope.
+    pub problems: Vec<String>,
+}
+
+impl ScopeReport {
+    /// Returns whether the scope stopped cleanly.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// The scopes disposed by [`Cortex::shutdown`], in the order they are disposed.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownReport {
+    pub scopes: Vec<ScopeReport>,
+    /// The plugin-level cleanups of the runtimes unloaded by the shutdown, reported under the id and the name of their plugin.
+    pub runtimes: Vec<ScopeReport>,
+}
+
+impl ShutdownReport {
+    /// Returns whether every scope and runtime stopped cleanly.
+    pub fn is_clean(&self) -> bool {
+        self.scopes.iter().chain(&self.runtimes).all(ScopeReport::is_clean)
+    }
 }
 
 pub struct Cortex {
@@ -417,6 +1208,10 @@ pub struct Cortex {
     pub parent: Weak<Cortex>,
     pub scope: Arc<Scope>,
     pub registry: Arc<Registry>,
+    /// The service names isolated by [`Cortex::isolate`], mapped to their separate slots.
+    isolated: HashMap<Arc<str>, Arc<str>>,
+    /// The filter of the events received by the listeners registered through this context.
+    filter: Filter,
     actor: LateInit<Addr<Cat>>,
 }
 
@@ -434,29 +1229,29 @@ impl PartialEq for Cortex {
 impl Eq for Cortex {}
 
 impl Cortex {
-    #[allow(invalid_value)]
-    #[allow(clippy::uninit_assumed_init)]
     pub fn new(config: Arc<impl KAny>) -> Arc<Self> {
+        Cortex::with_pool(config, PoolConfig::default())
+    }
+
+    /// Same as [`Cortex::new`], with the configuration of the worker pool shared by every scope.
+    pub fn with_pool(config: Arc<impl KAny>, pool: PoolConfig) -> Arc<Self> {
         let _ = tokio::runtime::Handle::try_current().expect("expect a tokio runtime");
-        let ctx = Arc::new_cyclic(|weak| Cortex {
-            root: weak.clone(),
-            parent: weak.clone(),
-            registry: Arc::new(Registry::new(weak.clone(), Arc::new(config.clone()))),
-            scope: Arc::new(unsafe { MaybeUninit::uninit().assume_init() }),
-            actor: LateInit::new(|| {
-                (Cat {
-                    cortex: weak.clone(),
-                })
-                .start()
-            }),
+        let ctx = Arc::new_cyclic(|weak: &Weak<Cortex>| {
+            let registry = Arc::new(Registry::new(weak.clone(), Arc::new(config.clone()), pool));
+            let runtime = MainScope::new(&registry, None::<()>);
+            let scope = Scope::new(runtime, weak, &registry, config, CancellationToken::new(), Weak::new());
+            let weak = weak.clone();
+            Cortex {
+                root: weak.clone(),
+                parent: weak.clone(),
+                registry,
+                scope,
+                isolated: HashMap::new(),
+                filter: Filter::default(),
+                actor: LateInit::new(move || (Cat { cortex: weak }).start()),
+            }
         });
-        let runtime = MainScope::new(ctx.clone(), None::<()>);
-        let scope = Scope::new(runtime, ctx.clone(), config, WORKER_COUNT);
-        let arc: *mut Cortex = Arc::as_ptr(&ctx) as *mut _;
-        mem::forget(mem::replace(
-            &mut (unsafe { arc.as_mut().unwrap() }).scope,
-            scope,
-        ));
+        Scope::attach(&ctx.scope);
         ctx
     }
 
@@ -464,13 +1259,123 @@ impl Cortex {
         &self.scope.runtime
     }
 
+    /// Derives a child context of this context, sharing the registry.
+    ///
+    /// `scope` receives the child context under construction, to create a scope bound to it.
+    fn derive(
+        self: &Arc<Self>,
+        scope: impl FnOnce(&Weak<Cortex>) -> Arc<Scope>,
+        isolated: HashMap<Arc<str>, Arc<str>>,
+        filter: Filter,
+    ) -> Arc<Cortex> {
+        Arc::new_cyclic(|weak: &Weak<Cortex>| {
+            let scope = scope(weak);
+            let weak = weak.clone();
+            Cortex {
+                root: self.root.clone(),
+                parent: Arc::downgrade(self),
+                scope,
+                registry: self.registry.clone(),
+                isolated,
+                filter,
+                actor: LateInit::new(move || (Cat { cortex: weak }).start()),
+            }
+        })
+    }
+
+    /// Creates a child context with its own scope, applying the given overrides.
+    ///
+    /// The child scope is forked from the root runtime and disposed together with the current scope,
+    /// and so are the plugins applied through the child context.
+    pub fn extend(self: &Arc<Self>, overrides: Overrides) -> result::Result<Arc<Cortex>> {
+        self.scope.assert_active()?;
+        let mut isolated = self.isolated.clone();
+        for name in overrides.isolate {
+            let key = Arc::from(format!("{name}#{}", self.registry.counter.fetch()));
+            isolated.insert(name, key);
+        }
+        let filter = match overrides.filter {
+            Some(filter) => self.filter.intersect(&filter),
+            None => self.filter.clone(),
+        };
+        let root = self.root.upgrade().ok_or(CrowdError::InactiveScope)?;
+        let ctx = self.derive(
+            |weak| {
+                Scope::new(
+                    root.scope.runtime.clone(),
+                    weak,
+                    &self.registry,
+                    self.scope.config.clone(),
+                    self.scope.lifecycle.token.child_token(),
+                    Arc::downgrade(&self.scope),
+                )
+            },
+            isolated,
+            filter,
+        );
+        // the child scope is still alive when the caller drops the child context
+        ctx.scope.own(ctx.clone());
+        Scope::attach(&ctx.scope);
+        let scope = Arc::downgrade(&ctx.scope);
+        let collected = self.collect("extend", move || {
+            if let Some(scope) = scope.upgrade() {
+                scope.dispose();
+            }
+        })?;
+        *ctx.scope.collected.lock().unwrap() = Some(collected);
+        Ok(ctx)
+    }
+
+    /// Creates a child context in which the service `name` resolves to a separate slot,
+    /// while the other services still resolve to the slots of this context.
+    ///
+    /// Unlike [`Cortex::extend`], the child context shares the scope of this context.
+    pub fn isolate(self: &Arc<Self>, name: impl Into<Arc<str>>) -> Arc<Cortex> {
+        let name = name.into();
+        let key = Arc::from(format!("{name}#{}", self.registry.counter.fetch()));
+        let mut isolated = self.isolated.clone();
+        isolated.insert(name, key);
+        self.derive(|_| self.scope.clone(), isolated, self.filter.clone())
+    }
+
+    /// Creates a child context whose listeners only receive the events accepted by `filter`,
+    /// in addition to the filter of this context.
+    ///
+    /// Unlike [`Cortex::extend`], the child context shares the scope of this context.
+    pub fn filtered(self: &Arc<Self>, filter: Filter) -> Arc<Cortex> {
+        self.derive(
+            |_| self.scope.clone(),
+            self.isolated.clone(),
+            self.filter.intersect(&filter),
+        )
+    }
+
+    /// Resolves the service `name` to the key of its slot in this context.
+    pub(crate) fn service_key(&self, name: &str) -> Arc<str> {
+        self.isolated
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| Arc::from(name))
+    }
+
+    /// Applies the plugin through this context, the plugin is disposed together with the current scope.
+    ///
+    /// The plugin receives a context derived from this one, whose scope is the returned fork.
     pub fn plug<T: Send + Sync + 'static + std::panic::UnwindSafe>(
-        &self,
+        self: &Arc<Self>,
         pluggable: impl Pluggable<T> + 'static,
         config: T,
     ) -> result::Result<Arc<Scope>> {
         self.scope.assert_active()?;
-        Ok(self.registry.plugin(pluggable, config))
+        let fork = self.registry.plugin(self.clone(), pluggable, config);
+        let weak = Arc::downgrade(&fork);
+        let collected = self.collect("plugin", move || {
+            if let Some(fork) = weak.upgrade() {
+                fork.dispose();
+            }
+        })?;
+        *fork.collected.lock().unwrap() = Some(collected);
+        Ok(fork)
     }
 
     fn actor(&self) -> &Addr<Cat> {
@@ -487,11 +1392,561 @@ impl Cortex {
         serial_result
     }
 
+    /// Inserts the handler built by `make` into the current scope with the given options.
+    fn listen(
+        &self,
+        options: ListenerOptions,
+        make: impl FnOnce(ListenerHandle) -> Arc<dyn Handler>,
+    ) -> result::Result<ListenerHandle> {
+        self.scope.assert_active()?;
+        let id = self.registry.counter.fetch();
+        let handle = ListenerHandle::new(id, &self.scope.handlers);
+        self.scope
+            .handlers
+            .insert(id, Registered::new(id, options, self.filter.clone(), make(handle.clone())));
+        Ok(handle)
+    }
+
+    /// Registers a listener for the events matched by `matcher`.
+    ///
+    /// The listener belongs to the current scope, and is removed when the scope is disposed.
+    pub fn on<F, Fut, R>(&self, matcher: EventMatcher, callback: F) -> result::Result<ListenerHandle>
+    where
+        F: Fn(EventMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + Sync + 'static,
+        R: EventResult + 'static,
+    {
+        self.on_with(matcher, ListenerOptions::default(), callback)
+    }
+
+    /// Registers a listener like [`Cortex::on`], ordered by the given [`ListenerOptions`].
+    pub fn on_with<F, Fut, R>(
+        &self,
+        matcher: EventMatcher,
+        options: ListenerOptions,
+        callback: F,
+    ) -> result::Result<ListenerHandle>
+    where
+        F: Fn(EventMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + Sync + 'static,
+        R: EventResult + 'static,
+    {
+        self.listen(options, |_| Arc::new(Listener::new(matcher, callback)))
+    }
+
+    /// Registers a listener that is removed after it is called for the first time.
+    ///
+    /// The listener belongs to the current scope, and is removed when the scope is disposed.
+    pub fn once<F, Fut, R>(&self, matcher: EventMatcher, callback: F) -> result::Result<ListenerHandle>
+    where
+        F: Fn(EventMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + Sync + 'static,
+        R: EventResult + 'static,
+    {
+        self.listen(ListenerOptions::default(), |handle| {
+            Arc::new(OnceListener::new(Listener::new(matcher, callback), handle))
+        })
+    }
+
+    /// Waits until an event matched by `matcher` is dispatched.
+    ///
+    /// Fails with [`CrowdError::Timeout`] if `timeout` elapsed first,
+    /// or with [`CrowdError::InactiveScope`] if the current scope is disposed first.
+    pub fn wait_for(
+        &self,
+        matcher: EventMatcher,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = result::Result<EventMessage>> + Send + Sync {
+        /// Removes the listener once the future is finished or dropped.
+        struct Unlisten(ListenerHandle);
+
+        impl Drop for Unlisten {
+            fn drop(&mut self) {
+                self.0.dispose();
+            }
+        }
+
+        let (tx, rx) = concurrent::oneshot::channel();
+        let tx = Mutex::new(Some(tx));
+        let handle = self
+            .once(matcher, move |evt| {
+                if let Some(tx) = tx.lock().unwrap().take() {
+                    let _ = tx.send(evt);
+                }
+                async {}
+            })
+            .map(Unlisten);
+        async move {
+            let _handle = handle?;
+            let received = match timeout {
+                None => rx.await,
+                Some(timeout) => match tokio::time::timeout(timeout, rx).await {
+                    Ok(received) => received,
+                    Err(_) => return Err(CrowdError::Timeout.into()),
+                },
+            };
+            received.map_err(|_| CrowdError::InactiveScope.into())
+        }
+    }
+
+    /// Registers a listener for the typed event `E`, whose arguments are checked at compile time.
+    ///
+    /// The listener belongs to the current scope, and is removed when the scope is disposed.
+    pub fn on_typed<E: EventNya>(&self, callback: impl HandlerTrait<E> + 'static) -> result::Result<ListenerHandle> {
+        self.listen(ListenerOptions::default(), |_| {
+            Arc::new(EventHandler::<E>::new(callback))
+        })
+    }
+
+    /// Runs `execute` and registers the cleanup it returns to the current scope.
+    ///
+    /// The cleanups of a scope run in the reverse order of their registration when the scope is disposed.
+    pub fn effect<F, D>(&self, execute: F) -> result::Result<DisposeHandle>
+    where
+        F: FnOnce() -> D,
+        D: FnOnce() + Send + Sync + 'static,
+    {
+        self.scope.assert_active()?;
+        let dispose = execute();
+        self.collect("effect", dispose)
+    }
+
+    /// Spawns a task bound to the current scope, which is aborted when the scope is disposed.
+    ///
+    /// The task is listed by [`Scope::tasks`] and cancelled by [`Scope::cancel_task`].
+    /// Once it is finished, its cleanup is forgotten before `finish` is called with its output,
+    /// and a panic in the task marks the scope `Failed`.
+    fn spawn_task<T: Send + 'static>(
+        &self,
+        name: impl Into<Arc<str>>,
+        fut: impl Future<Output = T> + Send + 'static,
+        finish: impl FnOnce(std::thread::Result<T>) + Send + 'static,
+    ) -> result::Result<DisposeHandle> {
+        self.scope.assert_active()?;
+        let name = name.into();
+        let id = self.registry.counter.fetch();
+        let lifecycle = &self.scope.lifecycle;
+        let token = lifecycle.token.clone();
+        let fut = async move {
+            tokio::select! {
+                output = AssertUnwindSafe(fut).catch_unwind() => Some(output),
+                _ = token.cancelled() => None,
+            }
+        };
+        let (abort, task) = lifecycle.tasker.track(name.clone(), fut);
+        let handle = lifecycle.collect(id, name.clone(), Cleanup::Sync(Box::new(move || abort.abort())));
+        let lifecycle = Arc::downgrade(lifecycle);
+        tokio::task::spawn(async move {
+            let output = task.await.flatten();
+            if let Some(lifecycle) = lifecycle.upgrade() {
+                lifecycle.forget(id);
+                if matches!(output, Some(Err(_))) {
+                    lifecycle.fail(&name);
+                }
+            }
+            if let Some(output) = output {
+                finish(output);
+            }
+        });
+        Ok(handle)
+    }
+
+    /// Spawns a task bound to the current scope, which is aborted when the scope is disposed.
+    ///
+    /// The returned handle resolves to the output of the task, and a panic in the task marks the scope `Failed`.
+    pub fn spawn<T: Send + 'static>(
+        &self,
+        name: impl Into<Arc<str>>,
+        fut: impl Future<Output = T> + Send + 'static,
+    ) -> result::Result<TaskHandle<T>> {
+        let name = name.into();
+        let (tx, rx) = concurrent::oneshot::channel();
+        let dispose = self.spawn_task(name.clone(), fut, {
+            let name = name.clone();
+            move |output| {
+                let output = output.map_err(|_| result::Error::PnpPanic(format!("panic in `{name}`")));
+                let _ = tx.send(output);
+            }
+        })?;
+        Ok(TaskHandle::new(name, dispose, rx))
+    }
+
+    /// Calls `callback` once after `delay`, unless the returned handle or the current scope is disposed first.
+    pub fn set_timeout(
+        &self,
+        delay: Duration,
+        callback: impl FnOnce() + Send + 'static,
+    ) -> result::Result<DisposeHandle> {
+        self.spawn_task("timeout", async move {
+            tokio::time::sleep(delay).await;
+            callback();
+        }, drop)
+    }
+
+    /// Calls `callback` every `period`, until the returned handle or the current scope is disposed.
+    pub fn set_interval(
+        &self,
+        period: Duration,
+        mut callback: impl FnMut() + Send + 'static,
+    ) -> result::Result<DisposeHandle> {
+        self.spawn_task("interval", async move {
+            let start = tokio::time::Instant::now() + period;
+            let mut interval = tokio::time::interval_at(start, period);
+            loop {
+                interval.tick().await;
+                callback();
+            }
+        }, drop)
+    }
+
+    /// Resolves once the current scope is disposed, so that long-running loops can stop cooperatively.
+    pub fn cancelled(&self) -> impl Future<Output = ()> + Send + Sync + 'static {
+        self.scope.lifecycle.token.clone().cancelled_owned()
+    }
+
+    /// Returns whether the current scope is disposed.
+    pub fn is_cancelled(&self) -> bool {
+        self.scope.lifecycle.token.is_cancelled()
+    }
+
+    /// Waits for `duration`, or fails with [`CrowdError::InactiveScope`] if the current scope is disposed first.
+    pub async fn sleep(&self, duration: Duration) -> result::Result<()> {
+        self.scope.assert_active()?;
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => Ok(()),
+            _ = self.scope.lifecycle.disposed() => Err(CrowdError::InactiveScope.into()),
+        }
+    }
+
+    /// Wraps `callback` to be called at most once every `delay`, see [`Throttled`].
+    pub fn throttle<A: Send + 'static>(
+        self: &Arc<Self>,
+        delay: Duration,
+        callback: impl Fn(A) + Send + Sync + 'static,
+    ) -> Throttled<A> {
+        Throttled::new(self, delay, callback)
+    }
+
+    /// Wraps `callback` to be called once the calls have stopped for `delay`, see [`Debounced`].
+    pub fn debounce<A: Send + 'static>(
+        self: &Arc<Self>,
+        delay: Duration,
+        callback: impl Fn(A) + Send + Sync + 'static,
+    ) -> Debounced<A> {
+        Debounced::new(self, delay, callback)
+    }
+
+    /// Registers a named cleanup to the current scope, see [`Cortex::effect`].
+    pub fn collect(
+        &self,
+        name: impl Into<Arc<str>>,
+        dispose: impl FnOnce() + Send + Sync + 'static,
+    ) -> result::Result<DisposeHandle> {
+        self.scope.assert_active()?;
+        let id = self.registry.counter.fetch();
+        Ok(self
+            .scope
+            .lifecycle
+            .collect(id, name.into(), Cleanup::Sync(Box::new(dispose))))
+    }
+
+    /// Registers a named asynchronous cleanup to the current scope.
+    ///
+    /// It is awaited by [`Scope::dispose_async`] within [`Scope::dispose_timeout`],
+    /// failures and timeouts are reported through `internal/error` and `internal/warn`.
+    pub fn collect_async<F, Fut>(&self, name: impl Into<Arc<str>>, dispose: F) -> result::Result<DisposeHandle>
+    where
+        F: FnOnce() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = color_eyre::Result<()>> + Send + Sync + 'static,
+    {
+        self.scope.assert_active()?;
+        let id = self.registry.counter.fetch();
+        let dispose = Cleanup::Async(Box::new(move || Box::pin(dispose()) as CleanupFuture));
+        Ok(self.scope.lifecycle.collect(id, name.into(), dispose))
+    }
+
+    /// Declares the service `name` to be of type `T`, so that it cannot be set with another type.
+    pub fn provide<T: KAny>(&self, name: impl Into<Arc<str>>) -> result::Result<()> {
+        Ok(self.registry.services.provide::<T>(self.service_key(&name.into()))?)
+    }
+
+    /// Sets the service `name`, replacing the current instance.
+    ///
+    /// The service is unset when the current scope is disposed, or when the returned handle is disposed.
+    pub fn set_service<T: KAny>(
+        &self,
+        name: impl Into<Arc<str>>,
+        value: Arc<T>,
+    ) -> result::Result<DisposeHandle> {
+        self.scope.assert_active()?;
+        let name = self.service_key(&name.into());
+        let old = self.registry.services.set(
+            name.clone(),
+            value.clone(),
+            Arc::downgrade(&self.scope),
+        )?;
+        let registry = Arc::downgrade(&self.registry);
+        let value: Arc<dyn KAny> = value;
+        let handle = self.collect(format!("service/{name}"), {
+            let name = name.clone();
+            let value = value.clone();
+            move || {
+                if let Some(registry) = registry.upgrade() {
+                    if registry.services.unset(&name, &value) {
+                        registry.service_changed(ServiceChange {
+                            name,
+                            old: Some(value),
+                            new: None,
+                        });
+                    }
+                }
+            }
+        })?;
+        self.registry.service_changed(ServiceChange {
+            name,
+            old,
+            new: Some(value),
+        });
+        Ok(handle)
+    }
+
+    /// Returns the current instance of the service `name`, if it is set with type `T`.
+    pub fn get_service<T: KAny>(&self, name: &str) -> Option<Arc<T>> {
+        self.registry.services.get::<T>(&self.service_key(name))
+    }
+
+    /// Returns a handle to the service `name`, which keeps resolving to the current instance
+    /// when the service is replaced, so the consumer does not have to restart.
+    ///
+    /// The current scope is no longer restarted when the service is replaced,
+    /// but it is still restarted when the service is set or removed.
+    pub fn service_ref<T: KAny>(&self, name: impl Into<Arc<str>>) -> ServiceRef<T> {
+        let key = self.service_key(&name.into());
+        self.scope.swappable.insert(key.clone());
+        ServiceRef::new(key, &self.registry)
+    }
+
+    /// Removes a listener registered by [`Cortex::on`], returns `false` if it has already been removed.
+    pub fn off(&self, handle: &ListenerHandle) -> bool {
+        handle.dispose()
+    }
+
+    /// Collects the handlers of every live scope that should be called with `msg`.
+    ///
+    /// The handlers are ordered by priority, then by registration order, across all scopes.
+    fn handlers(&self, msg: &EventMessage) -> Vec<Arc<dyn Handler>> {
+        let mut registered: Vec<Registered> = self
+            .registry
+            .scopes()
+            .iter()
+            .flat_map(|scope| {
+                scope
+                    .handlers
+                    .iter()
+                    .map(|entry| entry.value().clone())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|registered| registered.accepts(msg))
+            .collect();
+        registered.sort_by_key(Registered::order);
+        registered
+            .into_iter()
+            .map(|registered| registered.handler)
+            .collect()
+    }
+
+    /// Dispatches a built-in event serially in the background, even if the current scope is inactive.
+    ///
+    /// The handlers are collected before returning, and the events are dispatched one after another
+    /// in the order they are emitted, see [`Registry::dispatch`].
+    pub(crate) fn emit_internal(&self, evt: BuiltinEvent) {
+        let msg = EventMessage::internal(evt);
+        let handlers = self.handlers(&msg);
+        if handlers.is_empty() {
+            return;
+        }
+        self.registry.dispatch(msg, handlers);
+    }
+
+    pub(crate) async fn trigger(&self, trigger: ToTrigger) -> result::Result<Option<Box<dyn KAny>>> {
+        self.scope.assert_active()?;
+        let handlers = self.handlers(trigger.message());
+        match trigger {
+            ToTrigger::Emit(msg) => {
+                let msg = Arc::new(msg);
+                for handler in handlers {
+                    let msg = msg.clone();
+                    tokio::task::spawn(async move {
+                        handler.call(&msg).await;
+                    });
+                }
+                Ok(None)
+            }
+            ToTrigger::Parallel(msg) => {
+                futures::future::join_all(handlers.iter().map(|handler| handler.call(&msg))).await;
+                Ok(None)
+            }
+            ToTrigger::Bail(msg) => {
+                for handler in handlers {
+                    if let Some(result) = handler.call(&msg).await {
+                        return Ok(Some(result));
+                    }
+                }
+                Ok(None)
+            }
+            ToTrigger::Serial(msg) => {
+                for handler in handlers {
+                    handler.call(&msg).await;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Dispatches the event to every matching handler without waiting for them.
+    pub async fn emit(&self, msg: EventMessage) -> result::Result<()> {
+        self.trigger(ToTrigger::Emit(msg)).await.map(drop)
+    }
+
+    /// Dispatches the event to every matching handler concurrently,
+    /// and waits until all of them are finished.
+    pub async fn parallel(&self, msg: EventMessage) -> result::Result<()> {
+        self.trigger(ToTrigger::Parallel(msg)).await.map(drop)
+    }
+
+    /// Dispatches the event to the matching handlers one by one,
+    /// returns the first result that is not `None`.
+    pub async fn bail(&self, msg: EventMessage) -> result::Result<Option<Box<dyn KAny>>> {
+        self.trigger(ToTrigger::Bail(msg)).await
+    }
+
+    /// Dispatches the event to the matching handlers one by one,
+    /// waiting for each handler before calling the next one.
+    pub async fn serial_event(&self, msg: EventMessage) -> result::Result<()> {
+        self.trigger(ToTrigger::Serial(msg)).await.map(drop)
+    }
+
+    /// Dispatches the typed event `E` to every matching handler without waiting for them.
+    pub async fn emit_typed<E: EventNya>(&self, args: E::Args) -> result::Result<()> {
+        self.emit(EventMessage::typed::<E>(args)).await
+    }
+
+    /// Dispatches the typed event `E` to every matching handler concurrently,
+    /// and waits until all of them are finished.
+    pub async fn parallel_typed<E: EventNya>(&self, args: E::Args) -> result::Result<()> {
+        self.parallel(EventMessage::typed::<E>(args)).await
+    }
+
+    /// Dispatches the typed event `E` to the matching handlers one by one,
+    /// waiting for each handler before calling the next one.
+    pub async fn serial_typed<E: EventNya>(&self, args: E::Args) -> result::Result<()> {
+        self.serial_event(EventMessage::typed::<E>(args)).await
+    }
+
+    /// Dispatches the typed event `E` to the matching handlers one by one,
+    /// returns the first result that is not empty.
+    pub async fn bail_typed<E: EventNya>(&self, args: E::Args) -> result::Result<E::Result> {
+        self.bail(EventMessage::typed::<E>(args))
+            .await
+            .map(E::Result::from_result)
+    }
+
+    /// Runs until the root scope is disposed.
+    ///
+    /// On SIGINT or SIGTERM, every plugin scope is disposed before returning.
     pub async fn run(self: Arc<Cortex>) {
-        while !self.runtime().children.is_empty() {
-            tokio::task::yield_now().await
+        tokio::select! {
+            _ = self.scope.lifecycle.disposed() => {}
+            _ = shutdown_signal() => {
+                self.shutdown().await;
+            }
+        }
+    }
+
+    /// Disposes every scope and awaits their cleanups.
+    ///
+    /// Scopes are disposed depth-first, children before their parents,
+    /// and plugins before the scopes providing the services they depend on.
+    /// The plugin-level cleanups of the unloaded runtimes are awaited last.
+    pub async fn shutdown(&self) -> ShutdownReport {
+        let scopes = self.registry.scopes();
+        // a scope may be disposed by the cleanups of another one before its turn
+        let ids: Vec<_> = scopes.iter().map(|scope| scope.id()).collect();
+        let index = |scope: &Arc<Scope>| scopes.iter().position(|other| Arc::ptr_eq(other, scope));
+        // `before[i]` are the scopes to be disposed before `scopes[i]`
+        let mut before = vec![vec![]; scopes.len()];
+        for (i, scope) in scopes.iter().enumerate() {
+            if let Some(parent) = scope.parent().and_then(|parent| index(&parent)) {
+                before[parent].push(i);
+            }
+            for key in scope.dependencies() {
+                match self.registry.services.provider(&key).and_then(|provider| index(&provider)) {
+                    Some(provider) if provider != i => before[provider].push(i),
+                    _ => {}
+                }
+            }
+        }
+
+        fn visit(i: usize, before: &[Vec<usize>], visited: &mut [bool], order: &mut Vec<usize>) {
+            if mem::replace(&mut visited[i], true) {
+                return;
+            }
+            for &j in &before[i] {
+                visit(j, before, visited, order);
+            }
+            order.push(i);
+        }
+        let mut visited = vec![false; scopes.len()];
+        let mut order = Vec::with_capacity(scopes.len());
+        for i in 0..scopes.len() {
+            visit(i, &before, &mut visited, &mut order);
+        }
+
+        // the runtimes are disposed with their last fork, capture them before their ids are cleared
+        let mut runtimes: Vec<(usize, Arc<str>, Arc<MainScope>)> = vec![];
+        for scope in &scopes {
+            let runtime = scope.runtime();
+            let (Some(id), Some(plugin)) = (runtime.id(), runtime.plugin()) else {
+                continue;
+            };
+            if runtimes.iter().all(|(other, ..)| *other != id) {
+                runtimes.push((id, plugin.name(), runtime.clone()));
+            }
+        }
+
+        let mut report = ShutdownReport::default();
+        for i in order {
+            let (scope, Some(id)) = (&scopes[i], ids[i]) else {
+                continue;
+            };
+            let name = scope.name();
+            let (_, problems) = scope.dispose_reported().await;
+            report.scopes.push(ScopeReport { id, name, problems });
+        }
+        for (id, name, runtime) in runtimes {
+            let problems = runtime.cleaned().await;
+            report.runtimes.push(ScopeReport { id, name, problems });
+        }
+        report
+    }
+}
+
+/// Resolves on SIGINT or SIGTERM, only on SIGINT if SIGTERM cannot be listened to.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let Ok(mut terminate) = signal(SignalKind::terminate()) else {
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
         }
     }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
 }
 
 impl fmt::Display for Cortex {
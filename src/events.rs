@@ -1,19 +1,121 @@
 use std::any::TypeId;
-use std::sync::Arc;
-use async_trait::async_trait;
+use std::cmp::Reverse;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use dashmap::DashMap;
 use crate::any::KAny;
 use crate::context::{Cortex, MainScope, Scope, ScopeState};
+use crate::service::ServiceChange;
 
+/// The way an [`EventMessage`] is dispatched to the matching handlers.
 pub(crate) enum ToTrigger {
+    /// Calls every handler without waiting for them.
     Emit(EventMessage),
+    /// Calls every handler concurrently and waits for all of them.
     Parallel(EventMessage),
+    /// Calls the handlers one by one until one of them returns a result.
     Bail(EventMessage),
+    /// Calls the handlers one by one, waiting for each of them.
     Serial(EventMessage),
 }
 
+impl ToTrigger {
+    pub(crate) fn message(&self) -> &EventMessage {
+        match self {
+            ToTrigger::Emit(msg)
+            | ToTrigger::Parallel(msg)
+            | ToTrigger::Bail(msg)
+            | ToTrigger::Serial(msg) => msg,
+        }
+    }
+}
+
 pub enum EventMatcher {
     UserEvent(String),
-    BuiltinEvent(String)
+    BuiltinEvent(String),
+    /// Matches the user events whose name starts with the prefix.
+    UserPrefix(String),
+    /// Matches the user events whose name matches the pattern.
+    UserGlob(EventPattern),
+    /// Matches the built-in events whose name matches the pattern.
+    BuiltinGlob(EventPattern),
+}
+
+impl EventMatcher {
+    /// Creates an `EventMatcher` matching user events with a glob pattern, see [`EventPattern`].
+    pub fn glob(pattern: &str) -> Self {
+        EventMatcher::UserGlob(EventPattern::new(pattern))
+    }
+
+    /// Returns whether the given `EventMessage` is matched by this `EventMatcher`.
+    pub fn matches(&self, evt: &EventMessage) -> bool {
+        match (self, evt) {
+            (EventMatcher::UserEvent(name), EventMessage::User(evt)) => evt.name() == name,
+            (EventMatcher::BuiltinEvent(name), EventMessage::Builtin(evt)) => evt.name() == name,
+            (EventMatcher::UserPrefix(prefix), EventMessage::User(evt)) => evt.name().starts_with(prefix.as_str()),
+            (EventMatcher::UserGlob(pattern), EventMessage::User(evt)) => pattern.matches(evt.name()),
+            (EventMatcher::BuiltinGlob(pattern), EventMessage::Builtin(evt)) => pattern.matches(evt.name()),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Segment {
+    Exact(String),
+    /// `*`, matches exactly one segment.
+    Any,
+    /// `**`, matches any number of segments, including none.
+    Rest,
+}
+
+/// A glob pattern over `/`-separated event names, parsed once when the listener is registered.
+///
+/// A `*` segment matches exactly one segment, and a `**` segment matches any number of segments,
+/// so `"chat/*"` matches `"chat/message"`, while `"**"` matches every event.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct EventPattern {
+    segments: Vec<Segment>,
+}
+
+impl EventPattern {
+    pub fn new(pattern: &str) -> Self {
+        let mut segments = vec![];
+        for segment in pattern.split('/') {
+            let segment = match segment {
+                "*" => Segment::Any,
+                "**" => Segment::Rest,
+                exact => Segment::Exact(exact.to_string()),
+            };
+            // consecutive `**` are redundant
+            if segment == Segment::Rest && segments.last() == Some(&Segment::Rest) {
+                continue;
+            }
+            segments.push(segment);
+        }
+        Self { segments }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let parts: Vec<&str> = name.split('/').collect();
+        Self::match_segments(&self.segments, &parts)
+    }
+
+    fn match_segments(segments: &[Segment], parts: &[&str]) -> bool {
+        match (segments.first(), parts.first()) {
+            (None, None) => true,
+            (Some(Segment::Rest), _) => {
+                (0..=parts.len()).any(|skip| Self::match_segments(&segments[1..], &parts[skip..]))
+            }
+            (Some(Segment::Any), Some(_)) => Self::match_segments(&segments[1..], &parts[1..]),
+            (Some(Segment::Exact(exact)), Some(part)) if exact == part => {
+                Self::match_segments(&segments[1..], &parts[1..])
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone, Eq, PartialEq)]
@@ -26,16 +128,38 @@ pub enum InternalEvent {
     Warn(String),
     Debug(String),
     Error(String),
-    Service,
+    Service(ServiceChange),
+    /// A restart of the scope is scheduled by its restart policy, with the number of consecutive failures.
+    Restart(Arc<Scope>, u32),
     Listener,
 }
 
+impl InternalEvent {
+    /// Returns the name of this `InternalEvent`, prefixed with `internal/`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            InternalEvent::Fork(..) => "internal/fork",
+            InternalEvent::Runtime(..) => "internal/runtime",
+            InternalEvent::State(..) => "internal/state",
+            InternalEvent::Trace(..) => "internal/trace",
+            InternalEvent::Info(..) => "internal/info",
+            InternalEvent::Warn(..) => "internal/warn",
+            InternalEvent::Debug(..) => "internal/debug",
+            InternalEvent::Error(..) => "internal/error",
+            InternalEvent::Service(..) => "internal/service",
+            InternalEvent::Restart(..) => "internal/restart",
+            InternalEvent::Listener => "internal/listener",
+        }
+    }
+}
 
 #[derive(Clone)]
 pub enum BuiltinEvent {
     Fork(Arc<Cortex>, Arc<dyn KAny>),
-    Ready,
-    Dispose,
+    /// The plugin of the scope has been applied.
+    Ready(Arc<Scope>),
+    /// The scope is being disposed, dispatched before its own listeners are removed.
+    Dispose(Arc<Scope>),
     Internal(InternalEvent),
 }
 
@@ -44,8 +168,8 @@ impl PartialEq for BuiltinEvent {
         match (self, other) {
             (BuiltinEvent::Fork(cortex1, args1), BuiltinEvent::Fork(cortex2, args2)) =>
                 cortex1 == cortex2 && Arc::ptr_eq(args1, args2),
-            (BuiltinEvent::Ready, BuiltinEvent::Ready) => true,
-            (BuiltinEvent::Dispose, BuiltinEvent::Dispose) => true,
+            (BuiltinEvent::Ready(scope1), BuiltinEvent::Ready(scope2)) => scope1 == scope2,
+            (BuiltinEvent::Dispose(scope1), BuiltinEvent::Dispose(scope2)) => scope1 == scope2,
             (BuiltinEvent::Internal(event1), BuiltinEvent::Internal(event2)) =>
                 event1 == event2,
             _ => false
@@ -115,6 +239,15 @@ impl EventMessage {
         EventMessage::User(UserEvent(name, Arc::new(args)))
     }
 
+    /// Creates a new `EventMessage` representing the typed event `E` with the given arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `args`: The arguments for the typed event.
+    pub fn typed<E: EventNya>(args: E::Args) -> EventMessage {
+        EventMessage::User(UserEvent(E::name().to_string(), Arc::new(args)))
+    }
+
     /// Returns whether this `EventMessage` represents a built-in event.
     #[inline]
     pub fn is_builtin(&self) -> bool { matches!(self, EventMessage::Builtin(..)) }
@@ -170,12 +303,22 @@ impl EventMessage {
 }
 
 impl BuiltinEvent {
+    /// Returns the name of this `BuiltinEvent`, which is used by [`EventMatcher::BuiltinEvent`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinEvent::Fork(..) => "fork",
+            BuiltinEvent::Ready(..) => "ready",
+            BuiltinEvent::Dispose(..) => "dispose",
+            BuiltinEvent::Internal(evt) => evt.name(),
+        }
+    }
+
     /// Returns whether this `BuiltinEvent` represents a lifecycle event.
     ///
     /// A lifecycle event is one that corresponds to the creation or destruction of a scope, such as
     /// `Fork`, `Ready`, or `Dispose`.
     pub fn is_lifecycle(&self) -> bool {
-        matches!(*self, BuiltinEvent::Fork(..) | BuiltinEvent::Ready | BuiltinEvent::Dispose)
+        matches!(*self, BuiltinEvent::Fork(..) | BuiltinEvent::Ready(..) | BuiltinEvent::Dispose(..))
     }
 
     /// Returns whether this `BuiltinEvent` represents a strict lifecycle event.
@@ -183,7 +326,7 @@ impl BuiltinEvent {
     /// A strict lifecycle event is one that corresponds to the creation of a scope, such as
     /// `Fork`. This includes internal events like `Internal(Fork(...))`.
     pub fn is_strict_lifecycle(&self) -> bool {
-        matches!(*self, BuiltinEvent::Fork(..) | BuiltinEvent::Ready)
+        matches!(*self, BuiltinEvent::Fork(..) | BuiltinEvent::Ready(..))
     }
 
     /// Returns whether this `BuiltinEvent` represents an internal event.
@@ -194,19 +337,27 @@ impl BuiltinEvent {
     }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum LifecycleEvent {
     Fork,
     Ready,
     Dispose,
 }
 
-mod sealed {
-    pub trait Handler : Send + Sync {}
-}
-
-pub trait EventNya : Send + Sync {
-    type Args;
-    type Result = ();
+/// An event with statically typed arguments.
+///
+/// Typed events are delivered as user events named after [`EventNya::name`],
+/// so a dynamic listener can still subscribe to them through [`EventMatcher::UserEvent`].
+pub trait EventNya : Send + Sync + 'static {
+    type Args: KAny + Clone;
+    type Result: EventResult = ();
+
+    fn name() -> &'static str
+    where
+        Self: Sized,
+    {
+        std::any::type_name::<Self>()
+    }
 }
 
 impl EventNya for InternalEvent {
@@ -216,35 +367,292 @@ impl EventNya for LifecycleEvent {
     type Args = LifecycleEvent;
 }
 
-#[async_trait]
-pub(crate) trait HandlerTrait<E: EventNya>: sealed::Handler {
-    async fn handle(&mut self, data: E::Args) -> color_eyre::Result<E::Result>;
+/// The boxed future of a type-erased [`HandlerTrait`].
+pub(crate) type HandleFuture<E> = Pin<Box<dyn Future<Output = <E as EventNya>::Result> + Send + Sync>>;
+
+/// A listener of the typed event `E`, implemented by every `Fn(E::Args) -> impl Future<Output = E::Result>`.
+pub trait HandlerTrait<E: EventNya>: Fn(E::Args) -> <Self as HandlerTrait<E>>::Future + Send + Sync {
+    type Future: Future<Output = E::Result> + Send + Sync + 'static;
+}
+
+impl<E, F, Fut> HandlerTrait<E> for F
+where
+    E: EventNya,
+    F: Fn(E::Args) -> Fut + Send + Sync,
+    Fut: Future<Output = E::Result> + Send + Sync + 'static,
+{
+    type Future = Fut;
 }
 
-#[async_trait]
+/// The future returned by [`Handler::call`], it has to be `Sync` so that plugins can await it.
+pub(crate) type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Option<Box<dyn KAny>>> + Send + Sync + 'a>>;
+
 pub(crate) trait Handler : Send + Sync {
     fn should_call(&self, evt: &EventMessage) -> bool;
-    async fn call(&self, args: Box<dyn KAny>);
+    /// Invokes the handler with the event, returning `Some` if it produced a result.
+    ///
+    /// The result is only consumed by [`Cortex::bail`], other dispatch modes discard it.
+    fn call<'a>(&'a self, evt: &'a EventMessage) -> HandlerFuture<'a>;
+}
+
+/// The value returned by a listener, converted into the result of [`Handler::call`].
+pub trait EventResult: Send + Sized + 'static {
+    fn into_result(self) -> Option<Box<dyn KAny>>;
+    /// Converts the result of [`Cortex::bail`] back, mismatched results are treated as empty.
+    fn from_result(result: Option<Box<dyn KAny>>) -> Self;
+}
+
+impl EventResult for () {
+    fn into_result(self) -> Option<Box<dyn KAny>> {
+        None
+    }
+
+    fn from_result(_: Option<Box<dyn KAny>>) -> Self {}
 }
 
+impl<T: KAny> EventResult for Option<T> {
+    fn into_result(self) -> Option<Box<dyn KAny>> {
+        self.map(|val| Box::new(val) as Box<dyn KAny>)
+    }
+
+    fn from_result(result: Option<Box<dyn KAny>>) -> Self {
+        result.and_then(|val| val.downcast::<T>())
+    }
+}
+
+/// A listener registered by [`Cortex::on`].
+pub(crate) struct Listener<F> {
+    matcher: EventMatcher,
+    callback: F,
+}
+
+impl<F> Listener<F> {
+    pub(crate) fn new(matcher: EventMatcher, callback: F) -> Self {
+        Self { matcher, callback }
+    }
+}
+
+impl<F, Fut, R> Handler for Listener<F>
+where
+    F: Fn(EventMessage) -> Fut + Send + Sync,
+    Fut: Future<Output = R> + Send + Sync,
+    R: EventResult,
+{
+    fn should_call(&self, evt: &EventMessage) -> bool {
+        self.matcher.matches(evt)
+    }
+
+    fn call<'a>(&'a self, evt: &'a EventMessage) -> HandlerFuture<'a> {
+        Box::pin(async move { (self.callback)(evt.clone()).await.into_result() })
+    }
+}
+
+/// A listener registered by [`Cortex::once`], which removes itself after the first call.
+pub(crate) struct OnceListener<F> {
+    listener: Listener<F>,
+    handle: ListenerHandle,
+    called: AtomicBool,
+}
+
+impl<F> OnceListener<F> {
+    pub(crate) fn new(listener: Listener<F>, handle: ListenerHandle) -> Self {
+        Self { listener, handle, called: AtomicBool::new(false) }
+    }
+}
+
+impl<F> Handler for OnceListener<F>
+where
+    Listener<F>: Handler,
+{
+    fn should_call(&self, evt: &EventMessage) -> bool {
+        !self.called.load(Ordering::Acquire) && self.listener.should_call(evt)
+    }
+
+    fn call<'a>(&'a self, evt: &'a EventMessage) -> HandlerFuture<'a> {
+        if self.called.swap(true, Ordering::AcqRel) {
+            return Box::pin(async { None });
+        }
+        self.handle.dispose();
+        self.listener.call(evt)
+    }
+}
+
+/// Controls where a listener is placed in the dispatch order.
+///
+/// Listeners with a higher `priority` are called first, listeners with the same priority
+/// are called in registration order, unless `prepend` is set, which puts the listener
+/// in front of the listeners registered before it.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct ListenerOptions {
+    pub priority: i32,
+    pub prepend: bool,
+}
+
+impl ListenerOptions {
+    pub fn priority(self, priority: i32) -> Self {
+        Self { priority, ..self }
+    }
+
+    pub fn prepend(self) -> Self {
+        Self { prepend: true, ..self }
+    }
+}
+
+/// A predicate deciding which events the listeners of a context receive.
+#[derive(Clone)]
+pub struct Filter(Arc<dyn Fn(&EventMessage) -> bool + Send + Sync>);
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::all()
+    }
+}
+
+impl Filter {
+    pub fn new(predicate: impl Fn(&EventMessage) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(predicate))
+    }
+
+    /// A filter accepting every event.
+    pub fn all() -> Self {
+        Self::new(|_| true)
+    }
+
+    pub fn test(&self, evt: &EventMessage) -> bool {
+        (self.0)(evt)
+    }
+
+    /// Accepts the events accepted by both filters.
+    pub fn intersect(&self, other: &Filter) -> Filter {
+        let (this, other) = (self.clone(), other.clone());
+        Filter::new(move |evt| this.test(evt) && other.test(evt))
+    }
+
+    /// Accepts the events accepted by either filter.
+    pub fn union(&self, other: &Filter) -> Filter {
+        let (this, other) = (self.clone(), other.clone());
+        Filter::new(move |evt| this.test(evt) || other.test(evt))
+    }
+
+    /// Accepts the events accepted by this filter but not by `other`.
+    pub fn exclude(&self, other: &Filter) -> Filter {
+        let (this, other) = (self.clone(), other.clone());
+        Filter::new(move |evt| this.test(evt) && !other.test(evt))
+    }
+}
+
+/// A handler stored in [`Scope`], together with its position in the dispatch order
+/// and the filter of the context it is registered through.
+#[derive(Clone)]
+pub(crate) struct Registered {
+    priority: i32,
+    sequence: isize,
+    filter: Filter,
+    pub(crate) handler: Arc<dyn Handler>,
+}
+
+impl Registered {
+    pub(crate) fn new(
+        id: usize,
+        options: ListenerOptions,
+        filter: Filter,
+        handler: Arc<dyn Handler>,
+    ) -> Self {
+        let sequence = id as isize;
+        Self {
+            priority: options.priority,
+            sequence: if options.prepend { -sequence } else { sequence },
+            filter,
+            handler,
+        }
+    }
+
+    pub(crate) fn accepts(&self, evt: &EventMessage) -> bool {
+        self.filter.test(evt) && self.handler.should_call(evt)
+    }
+
+    pub(crate) fn order(&self) -> (Reverse<i32>, isize) {
+        (Reverse(self.priority), self.sequence)
+    }
+}
+
+pub(crate) type Handlers = DashMap<usize, Registered>;
+
+/// A handle to a listener registered by [`Cortex::on`].
+///
+/// The listener is removed when [`ListenerHandle::dispose`] is called,
+/// or when the scope it belongs to is disposed.
+#[derive(Clone)]
+pub struct ListenerHandle {
+    id: usize,
+    handlers: Weak<Handlers>,
+}
+
+impl ListenerHandle {
+    pub(crate) fn new(id: usize, handlers: &Arc<Handlers>) -> Self {
+        Self { id, handlers: Arc::downgrade(handlers) }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Removes the listener, returns `false` if it has already been removed.
+    pub fn dispose(&self) -> bool {
+        self.handlers
+            .upgrade()
+            .map(|handlers| handlers.remove(&self.id).is_some())
+            .unwrap_or(false)
+    }
+}
+
+/// A listener registered by [`Cortex::on_typed`].
 pub struct EventHandler<E: EventNya> {
-    inner: Box<dyn HandlerTrait<E>>,
+    inner: Box<dyn Fn(E::Args) -> HandleFuture<E> + Send + Sync>,
+}
+
+impl<E: EventNya> EventHandler<E> {
+    pub(crate) fn new(handler: impl HandlerTrait<E> + 'static) -> Self {
+        Self { inner: Box::new(move |args| Box::pin(handler(args))) }
+    }
+
+    /// Extracts the typed arguments of `E` from the event, downcasting them via [`KAny`].
+    fn args(evt: &EventMessage) -> Option<E::Args> {
+        match evt {
+            EventMessage::User(evt) => evt.1.downcast_ref::<E::Args>().cloned(),
+            EventMessage::Builtin(BuiltinEvent::Internal(evt)) => {
+                (evt as &dyn KAny).downcast_ref::<E::Args>().cloned()
+            }
+            EventMessage::Builtin(evt) => {
+                let evt = match evt {
+                    BuiltinEvent::Fork(..) => LifecycleEvent::Fork,
+                    BuiltinEvent::Ready(..) => LifecycleEvent::Ready,
+                    BuiltinEvent::Dispose(..) => LifecycleEvent::Dispose,
+                    BuiltinEvent::Internal(_) => unreachable!(),
+                };
+                (&evt as &dyn KAny).downcast_ref::<E::Args>().cloned()
+            }
+        }
+    }
 }
 
-#[async_trait]
-impl<E: EventNya + 'static> Handler for EventHandler<E> {
+impl<E: EventNya> Handler for EventHandler<E> {
     fn should_call(&self, evt: &EventMessage) -> bool {
         match evt {
             EventMessage::Builtin(_) if TypeId::of::<E>() == TypeId::of::<LifecycleEvent>() => evt.is_strict_lifecycle(),
             EventMessage::Builtin(_) if TypeId::of::<E>() == TypeId::of::<InternalEvent>() => evt.is_internal(),
             EventMessage::Builtin(_) if TypeId::of::<E>() == TypeId::of::<BuiltinEvent>() => true,
             EventMessage::User(_)  if TypeId::of::<E>() == TypeId::of::<UserEvent>() => true,
+            EventMessage::User(UserEvent(name, args)) => name == E::name() && args.is::<E::Args>(),
             _ => false
         }
     }
 
-    async fn call(&self, args: Box<dyn KAny>) {
-        todo!()
+    fn call<'a>(&'a self, evt: &'a EventMessage) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let args = Self::args(evt)?;
+            (self.inner)(args).await.into_result()
+        })
     }
 }
 
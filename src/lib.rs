@@ -18,13 +18,22 @@ mod registry;
 mod events;
 mod cat;
 mod utils;
-mod result;
+pub mod result;
+mod service;
 mod tasker;
+mod timer;
 
 pub mod prelude {
-    pub use crate::context::Cortex;
+    pub use crate::context::{Cortex, DisposeHandle, Overrides, ScopeReport, ShutdownReport};
     pub use crate::plugin::Plugin;
-    pub use crate::events::EventMessage;
+    pub use crate::pnp::{Backoff, Inject, Pluggable, RestartPolicy};
+    pub use crate::service::{ServiceChange, ServiceRef};
+    pub use crate::tasker::{PoolConfig, Priority, TaskHandle, TaskInfo, TaskStatus, WorkerPool};
+    pub use crate::timer::{Debounced, Throttled};
+    pub use crate::events::{
+        EventMatcher, EventMessage, EventNya, EventPattern, EventResult, Filter, HandlerTrait, ListenerHandle,
+        ListenerOptions, UserEvent,
+    };
 }
 
 #[cfg(test)]
@@ -3,13 +3,19 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use crate::any::KAny;
 use crate::context::Cortex;
-use crate::pnp::{Hot, Pluggable};
+use crate::pnp::{Hot, Inject, Pluggable, RestartPolicy};
 
 pub type Id = u128;
 
 #[async_trait]
 pub trait Plugin: Send + Sync + UnwindSafe {
     fn name(&self) -> Arc<str>;
+    fn inject(&self) -> Inject {
+        Inject::default()
+    }
+    fn restart(&self) -> RestartPolicy {
+        RestartPolicy::default()
+    }
     async fn apply(&self, cortex: Arc<Cortex>) -> color_eyre::Result<()>;
     async fn hot(&self, config: Box<dyn KAny>) -> Result<Hot, ()>;
     fn identifier(&self) -> Id;
@@ -42,6 +48,8 @@ impl PartialEq for dyn Plugin {
 
 pub(crate) struct Plug<T: KAny> {
     name: Arc<str>,
+    inject: Inject,
+    restart: RestartPolicy,
     inner: Box<dyn Pluggable<T>>,
     id: Id,
 }
@@ -50,6 +58,8 @@ impl<T: KAny> Plug<T> {
     pub(crate) fn new<P: Pluggable<T> + 'static>(pluggable: P) -> Self {
         Plug {
             name: Arc::from(P::name()),
+            inject: P::inject(),
+            restart: P::restart(),
             id: P::apply as usize as u128,
             inner: Box::new(pluggable),
         }
@@ -65,6 +75,14 @@ impl<T: KAny + Send + Sync + UnwindSafe> Plugin for Plug<T> {
         self.name.clone()
     }
 
+    fn inject(&self) -> Inject {
+        self.inject.clone()
+    }
+
+    fn restart(&self) -> RestartPolicy {
+        self.restart
+    }
+
     async fn apply(&self, cortex: Arc<Cortex>) -> color_eyre::Result<()> {
         self.inner.apply(cortex).await
     }
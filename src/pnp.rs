@@ -1,6 +1,7 @@
 use std::future::Future;
 use std::panic::UnwindSafe;
 use std::sync::Arc;
+use std::time::Duration;
 use async_trait::async_trait;
 use crate::context::Cortex;
 
@@ -9,11 +10,138 @@ pub enum Hot {
     Updated,
 }
 
+/// The services a plugin depends on.
+///
+/// A plugin is not applied until all of its `required` services are set,
+/// and it is restarted whenever one of its `required` or `optional` services changes.
+#[derive(Clone, Default, Debug)]
+pub struct Inject {
+    pub required: Vec<Arc<str>>,
+    pub optional: Vec<Arc<str>>,
+}
+
+impl Inject {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn required(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.required.push(name.into());
+        self
+    }
+
+    pub fn optional(mut self, name: impl Into<Arc<str>>) -> Self {
+        self.optional.push(name.into());
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.required.iter().chain(&self.optional).any(|service| &**service == name)
+    }
+}
+
+/// An exponential backoff between the restarts of a plugin.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub factor: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(30))
+    }
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            factor: 2,
+        }
+    }
+
+    pub fn factor(mut self, factor: u32) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    /// Returns the delay before the restart following `failures` consecutive failures.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exp = self.factor.saturating_pow(failures.saturating_sub(1));
+        self.initial.saturating_mul(exp).min(self.max)
+    }
+}
+
+/// How a plugin is restarted once its `apply` has returned.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum RestartPolicy {
+    /// The plugin is only restarted manually or by its services.
+    #[default]
+    Never,
+    /// Restarts the plugin when it fails, up to `max_attempts` consecutive times.
+    OnFailure { max_attempts: u32, backoff: Backoff },
+    /// Restarts the plugin whenever its `apply` returns, succeeded or not, without limiting the attempts.
+    ///
+    /// Meant for plugins whose `apply` runs until [`Cortex::cancelled`](crate::context::Cortex::cancelled) resolves.
+    Always { backoff: Backoff },
+}
+
+impl RestartPolicy {
+    pub fn on_failure(max_attempts: u32) -> Self {
+        RestartPolicy::OnFailure {
+            max_attempts,
+            backoff: Backoff::default(),
+        }
+    }
+
+    pub fn always() -> Self {
+        RestartPolicy::Always {
+            backoff: Backoff::default(),
+        }
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        match &mut self {
+            RestartPolicy::Never => {}
+            RestartPolicy::OnFailure { backoff: prev, .. } | RestartPolicy::Always { backoff: prev } => {
+                *prev = backoff
+            }
+        }
+        self
+    }
+
+    /// Returns the delay before restarting the plugin, or `None` if it should not be restarted.
+    pub(crate) fn next(&self, failed: bool, failures: u32) -> Option<Duration> {
+        match self {
+            RestartPolicy::OnFailure { max_attempts, backoff } if failed && failures <= *max_attempts => {
+                Some(backoff.delay(failures))
+            }
+            RestartPolicy::Always { backoff } => Some(backoff.delay(failures)),
+            _ => None,
+        }
+    }
+}
+
 #[async_trait]
 pub trait Pluggable<T>: Send + Sync + UnwindSafe {
     fn name() -> &'static str
     where
         Self: Sized;
+    fn inject() -> Inject
+    where
+        Self: Sized,
+    {
+        Inject::default()
+    }
+    fn restart() -> RestartPolicy
+    where
+        Self: Sized,
+    {
+        RestartPolicy::default()
+    }
     async fn apply(&self, cortex: Arc<Cortex>) -> color_eyre::Result<()>;
     async fn hot(&self, config: T) -> Result<Hot, ()>;
 }
@@ -1,11 +1,17 @@
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::Relaxed;
+use std::panic::AssertUnwindSafe;
 use std::sync::{Arc, Weak};
 use dashmap::DashMap;
+use futures::FutureExt;
+use tokio::sync::mpsc;
 use crate::any::KAny;
 use crate::context::{Cortex, MainScope, Scope};
+use crate::events::{BuiltinEvent, EventMessage, Handler, InternalEvent};
 use crate::plugin::{Id, Plug, Plugin};
 use crate::pnp::Pluggable;
+use crate::service::{ServiceChange, Services};
+use crate::tasker::{PoolConfig, WorkerPool};
 
 pub(crate) struct Counter {
     counter: AtomicUsize,
@@ -33,17 +39,39 @@ pub struct Registry {
     pub(crate) context: Weak<Cortex>,
     pub(crate) counter: Counter,
     pub(crate) entries: DashMap<Id, Weak<MainScope>>,
+    pub(crate) services: Services,
+    pub(crate) pool: Arc<WorkerPool>,
+    /// The built-in events waiting to be dispatched, with the handlers collected when they are emitted.
+    internal: mpsc::UnboundedSender<(EventMessage, Vec<Arc<dyn Handler>>)>,
 }
 
 impl Registry {
-    pub fn new(ctx: Weak<Cortex>, config: Arc<impl KAny>) -> Self {
+    pub fn new(ctx: Weak<Cortex>, config: Arc<impl KAny>, pool: PoolConfig) -> Self {
+        let (internal, mut queue) = mpsc::unbounded_channel::<(EventMessage, Vec<Arc<dyn Handler>>)>();
+        tokio::task::spawn(async move {
+            while let Some((msg, handlers)) = queue.recv().await {
+                for handler in handlers {
+                    let _ = AssertUnwindSafe(handler.call(&msg)).catch_unwind().await;
+                }
+            }
+        });
         Self {
             context: ctx,
             counter: Counter::default(),
             entries: DashMap::new(),
+            services: Services::default(),
+            pool: WorkerPool::new(pool),
+            internal,
         }
     }
 
+    /// Queues a built-in event, the events are dispatched one after another in the order they are queued.
+    ///
+    /// A handler of a built-in event holds back the later ones, so it should not wait for them.
+    pub(crate) fn dispatch(&self, msg: EventMessage, handlers: Vec<Arc<dyn Handler>>) {
+        let _ = self.internal.send((msg, handlers));
+    }
+
     pub fn get(&self, plugin: &dyn Plugin) -> Option<Arc<MainScope>> {
         let identifier = plugin.identifier();
         let runtime = self.entries.get(&identifier);
@@ -70,25 +98,57 @@ impl Registry {
         }
     }
 
-    fn ctx(&self) -> Arc<Cortex> {
-        self.context.upgrade().unwrap()
+    /// Collects every live scope, including the forks of the root runtime.
+    pub(crate) fn scopes(&self) -> Vec<Arc<Scope>> {
+        let mut scopes = self.ctx().map(|ctx| ctx.runtime().children()).unwrap_or_default();
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.value().upgrade())
+            .for_each(|rt| scopes.extend(rt.children()));
+        scopes
     }
 
-    pub fn plugin<T: KAny + std::panic::UnwindSafe>(&self, pluggable: impl Pluggable<T> + 'static, config: T) -> Arc<Scope> {
+    /// Emits `internal/service`, and restarts every scope whose plugin depends on the service.
+    ///
+    /// The scopes reading the service through a `ServiceRef` are only restarted when it is set or removed.
+    pub(crate) fn service_changed(&self, change: ServiceChange) {
+        let key = change.name.clone();
+        let replaced = change.old.is_some() && change.new.is_some();
+        if let Some(ctx) = self.ctx() {
+            ctx.emit_internal(BuiltinEvent::Internal(InternalEvent::Service(change)));
+        }
+        self.scopes()
+            .into_iter()
+            .filter(|scope| scope.depends_on(&key) && !(replaced && scope.is_swappable(&key)))
+            .for_each(|scope| scope.restart());
+    }
+
+    /// Returns the root context, `None` once it is dropped while some forks are still alive.
+    fn ctx(&self) -> Option<Arc<Cortex>> {
+        self.context.upgrade()
+    }
+
+    /// Forks the plugin under `parent`, the context the plugin is applied through.
+    pub fn plugin<T: KAny + std::panic::UnwindSafe>(
+        &self,
+        parent: Arc<Cortex>,
+        pluggable: impl Pluggable<T> + 'static,
+        config: T,
+    ) -> Arc<Scope> {
         let plugged = Plug::new(pluggable);
         match self.get(&plugged) {
             None => {
                 let shared = Arc::new(config);
-                let rt = MainScope::new(self.ctx(), Some(plugged));
+                let rt = MainScope::new(self, Some(plugged));
                 self.set(rt.plugin().unwrap(), Arc::downgrade(&rt));
-                rt.fork(self.ctx(), shared)
-            }
-            Some(rt) if rt.alone => {
-                // serial
-                todo!("serial warning");
+                if let Some(ctx) = self.ctx() {
+                    ctx.emit_internal(BuiltinEvent::Internal(InternalEvent::Runtime(rt.clone())));
+                }
+                rt.fork(parent, shared)
             }
+            // the plugin may be applied through several contexts, e.g. one per isolated tenant
             Some(rt) => {
-                rt.fork(self.ctx(), Arc::new(config))
+                rt.fork(parent, Arc::new(config))
             }
         }
     }
@@ -5,7 +5,15 @@ pub enum CrowdError {
     #[error("cannot create effect in a inactive scope")]
     InactiveScope,
     #[error("expect a Pluggable (FnOnce(Arc<Cortex>) -> color_eyre::Result, e.g.)")]
-    InvalidPlug
+    InvalidPlug,
+    #[error("timed out before the event arrives")]
+    Timeout,
+    #[error("the service is provided with another type")]
+    ServiceType,
+    #[error("the task is cancelled before it completes")]
+    Cancelled,
+    #[error("the task queue of the worker pool is full")]
+    QueueFull,
 }
 
 pub(crate) type Result<T, E = Error> = ::std::result::Result<T, E>;
@@ -0,0 +1,148 @@
+use std::any::TypeId;
+use std::marker::PhantomData;
+use std::sync::{Arc, Weak};
+use dashmap::DashMap;
+use crate::any::KAny;
+use crate::context::Scope;
+use crate::registry::Registry;
+use crate::result::CrowdError;
+
+#[derive(Default)]
+struct Slot {
+    tid: Option<TypeId>,
+    value: Option<Arc<dyn KAny>>,
+    /// The scope which has set the current instance.
+    provider: Weak<Scope>,
+}
+
+impl Slot {
+    fn accepts<T: KAny>(&self) -> bool {
+        self.tid.is_none_or(|tid| tid == TypeId::of::<T>())
+    }
+}
+
+/// The services shared between plugins, keyed by name.
+#[derive(Default)]
+pub(crate) struct Services {
+    slots: DashMap<Arc<str>, Slot>,
+}
+
+impl Services {
+    /// Declares the service `name` to be of type `T`.
+    pub(crate) fn provide<T: KAny>(&self, name: Arc<str>) -> Result<(), CrowdError> {
+        let mut slot = self.slots.entry(name).or_default();
+        match &slot.value {
+            Some(value) if !value.is::<T>() => Err(CrowdError::ServiceType),
+            _ => {
+                slot.tid = Some(TypeId::of::<T>());
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets the service `name` provided by `provider`, returns the replaced instance.
+    pub(crate) fn set<T: KAny>(
+        &self,
+        name: Arc<str>,
+        value: Arc<T>,
+        provider: Weak<Scope>,
+    ) -> Result<Option<Arc<dyn KAny>>, CrowdError> {
+        let mut slot = self.slots.entry(name).or_default();
+        if !slot.accepts::<T>() {
+            return Err(CrowdError::ServiceType);
+        }
+        slot.provider = provider;
+        Ok(slot.value.replace(value))
+    }
+
+    /// Unsets the service `name`, if it is still the given instance.
+    pub(crate) fn unset(&self, name: &str, value: &Arc<dyn KAny>) -> bool {
+        match self.slots.get_mut(name) {
+            Some(mut slot) if slot.value.as_ref().is_some_and(|current| Arc::ptr_eq(current, value)) => {
+                slot.value = None;
+                slot.provider = Weak::new();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn has(&self, name: &str) -> bool {
+        self.slots.get(name).is_some_and(|slot| slot.value.is_some())
+    }
+
+    /// Returns the scope which has set the service `name`.
+    pub(crate) fn provider(&self, name: &str) -> Option<Arc<Scope>> {
+        self.slots.get(name).and_then(|slot| slot.provider.upgrade())
+    }
+
+    pub(crate) fn get<T: KAny>(&self, name: &str) -> Option<Arc<T>> {
+        self.slots
+            .get(name)
+            .and_then(|slot| slot.value.clone())
+            .and_then(|value| value.downcast_arc::<T>())
+    }
+}
+
+/// The change of a service slot, carried by `internal/service`.
+#[derive(Clone)]
+pub struct ServiceChange {
+    /// The key of the slot, which is the service name unless it is isolated by
+    /// [`Cortex::isolate`](crate::context::Cortex::isolate).
+    pub name: Arc<str>,
+    pub old: Option<Arc<dyn KAny>>,
+    pub new: Option<Arc<dyn KAny>>,
+}
+
+impl PartialEq for ServiceChange {
+    fn eq(&self, other: &Self) -> bool {
+        fn same(a: &Option<Arc<dyn KAny>>, b: &Option<Arc<dyn KAny>>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        self.name == other.name && same(&self.old, &other.old) && same(&self.new, &other.new)
+    }
+}
+
+impl Eq for ServiceChange {}
+
+/// A handle to the service `name`, which always resolves to its current instance.
+pub struct ServiceRef<T> {
+    name: Arc<str>,
+    registry: Weak<Registry>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for ServiceRef<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            registry: self.registry.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: KAny> ServiceRef<T> {
+    pub(crate) fn new(name: Arc<str>, registry: &Arc<Registry>) -> Self {
+        Self {
+            name,
+            registry: Arc::downgrade(registry),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the current instance, if it is set with type `T`.
+    pub fn get(&self) -> Option<Arc<T>> {
+        self.registry
+            .upgrade()
+            .and_then(|registry| registry.services.get::<T>(&self.name))
+    }
+}
@@ -1,40 +1,449 @@
 use std::cell::UnsafeCell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
 use std::future::Future;
 use std::iter;
+use std::mem;
 use std::pin::Pin;
-use std::sync::{Arc, Once};
+use std::sync::{Arc, Mutex, Once};
+use std::panic::AssertUnwindSafe;
 use std::sync::atomic::AtomicUsize;
-use tokio::sync::Notify;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use dashmap::DashMap;
+use futures::future::{AbortHandle, Abortable};
+use futures::FutureExt;
+use tokio::sync::{oneshot, Notify};
 use tokio::task::JoinHandle;
+use crate::context::DisposeHandle;
+use crate::result;
+use crate::result::CrowdError;
 
-pub struct Tasker {
-    sender: flume::Sender<Task>,
+/// A handle to a task spawned by [`Cortex::spawn`](crate::context::Cortex::spawn),
+/// which resolves to the output of the task.
+///
+/// Resolves to [`CrowdError::Cancelled`] if the task is aborted or its scope is disposed first,
+/// or to [`result::Error::PnpPanic`] if the task panics.
+pub struct TaskHandle<T> {
+    name: Arc<str>,
+    dispose: DisposeHandle,
+    output: oneshot::Receiver<result::Result<T>>,
+}
+
+impl<T> TaskHandle<T> {
+    pub(crate) fn new(
+        name: Arc<str>,
+        dispose: DisposeHandle,
+        output: oneshot::Receiver<result::Result<T>>,
+    ) -> Self {
+        Self { name, dispose, output }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Aborts the task, returns `false` if it has already finished.
+    pub fn abort(&self) -> bool {
+        self.dispose.dispose()
+    }
+}
+
+impl<T> Future for TaskHandle<T> {
+    type Output = result::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.output)
+            .poll(cx)
+            .map(|output| output.unwrap_or_else(|_| Err(CrowdError::Cancelled.into())))
+    }
+}
+
+/// The priority of a task, tasks with a higher priority are picked first by the workers,
+/// whichever scope they belong to.
+///
+/// The lifecycle tasks of a scope, such as applying its plugin, are scheduled with [`Priority::High`].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TaskStatus {
+    Pending,
+    Running,
+}
+
+/// A snapshot of a task scheduled into a [`Tasker`].
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    pub id: usize,
+    pub name: Arc<str>,
+    /// The id of the scope owning the task.
+    pub scope: usize,
+    pub priority: Priority,
+    pub status: TaskStatus,
+    pub queued_at: Instant,
+    pub started_at: Option<Instant>,
+}
+
+struct Tracked {
+    info: TaskInfo,
+    abort: AbortHandle,
+}
+
+/// A task waiting in the queue, ordered by priority, then by the order it is scheduled.
+struct Queued {
+    id: usize,
+    task: Task,
+}
+
+impl Queued {
+    fn key(&self) -> (Priority, Reverse<usize>) {
+        (self.task.priority, Reverse(self.id))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// The configuration of the [`WorkerPool`] shared by every scope of a [`Cortex`](crate::context::Cortex).
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// The number of workers.
+    pub workers: usize,
+    /// The maximum number of pending tasks of a scope, unbounded if `None`.
+    pub queue_bound: Option<usize>,
+    /// The maximum number of tasks of a scope running at the same time.
+    pub scope_concurrency: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: std::thread::available_parallelism().map_or(4, usize::from),
+            queue_bound: None,
+            scope_concurrency: 1,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn queue_bound(mut self, bound: usize) -> Self {
+        self.queue_bound = Some(bound);
+        self
+    }
+
+    pub fn scope_concurrency(mut self, concurrency: usize) -> Self {
+        self.scope_concurrency = concurrency.max(1);
+        self
+    }
+}
+
+/// The pending tasks of a scope.
+#[derive(Default)]
+struct Lane {
+    heap: BinaryHeap<Queued>,
+    running: usize,
+    /// Whether the lane is waiting in `Lanes::ready`.
+    ready: bool,
+}
+
+#[derive(Default)]
+struct Lanes {
+    lanes: HashMap<usize, Lane>,
+    /// The scopes with pending tasks which may run, picked in turn so that no scope starves the others.
+    ready: VecDeque<usize>,
+}
+
+impl Lanes {
+    fn wake(&mut self, owner: usize, concurrency: usize) {
+        let Some(lane) = self.lanes.get_mut(&owner) else {
+            return;
+        };
+        if !lane.ready && !lane.heap.is_empty() && lane.running < concurrency {
+            lane.ready = true;
+            self.ready.push_back(owner);
+        }
+    }
+}
+
+struct Queue {
+    config: PoolConfig,
+    lanes: Mutex<Lanes>,
+    available: Notify,
+    counter: AtomicUsize,
+    tasks: DashMap<usize, Tracked>,
+}
+
+impl Queue {
+    fn push(&self, owner: usize, mut task: Task) -> Result<(), CrowdError> {
+        let id = self.counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let mut lanes = self.lanes.lock().unwrap();
+        // the cancelled tasks are left in the heap until they are skipped, they do not count
+        let pending = lanes.lanes.get(&owner).map_or(0, |lane| {
+            lane.heap.iter().filter(|queued| self.tasks.contains_key(&queued.id)).count()
+        });
+        if self.config.queue_bound.is_some_and(|bound| pending >= bound) {
+            return Err(CrowdError::QueueFull);
+        }
+        let (abort, registration) = AbortHandle::new_pair();
+        let fut = std::mem::replace(&mut task.fut, Box::new(futures::future::ready(())));
+        task.fut = Box::new(Abortable::new(fut, registration).map(|_| ()));
+        self.tasks.insert(id, Tracked {
+            info: TaskInfo {
+                id,
+                name: task.name.clone(),
+                scope: owner,
+                priority: task.priority,
+                status: TaskStatus::Pending,
+                queued_at: Instant::now(),
+                started_at: None,
+            },
+            abort,
+        });
+        lanes.lanes.entry(owner).or_default().heap.push(Queued { id, task });
+        lanes.wake(owner, self.config.scope_concurrency);
+        drop(lanes);
+        self.available.notify_one();
+        Ok(())
+    }
+
+    /// Takes the next task of the ready scope whose next task has the highest priority, skipping the cancelled tasks.
+    ///
+    /// The ready scopes are served in turn among those whose next tasks share the same priority.
+    fn take(&self) -> Option<(usize, usize, Task)> {
+        let mut guard = self.lanes.lock().unwrap();
+        let Lanes { lanes, ready } = &mut *guard;
+        // the cancelled tasks are left in the heaps, drop those hiding the next task of a ready scope
+        ready.retain(|owner| {
+            let Some(lane) = lanes.get_mut(owner) else {
+                return false;
+            };
+            while lane.heap.peek().is_some_and(|queued| !self.tasks.contains_key(&queued.id)) {
+                lane.heap.pop();
+            }
+            lane.ready = !lane.heap.is_empty();
+            lane.ready
+        });
+        let mut picked: Option<(usize, Priority)> = None;
+        for (index, owner) in ready.iter().enumerate() {
+            let priority = lanes[owner].heap.peek().unwrap().task.priority;
+            if picked.is_none_or(|(_, highest)| priority > highest) {
+                picked = Some((index, priority));
+            }
+        }
+        let owner = ready.remove(picked?.0).unwrap();
+        let lane = lanes.get_mut(&owner).unwrap();
+        lane.ready = false;
+        let Queued { id, task } = lane.heap.pop().unwrap();
+        if let Some(mut tracked) = self.tasks.get_mut(&id) {
+            tracked.info.status = TaskStatus::Running;
+            tracked.info.started_at = Some(Instant::now());
+        }
+        lane.running += 1;
+        guard.wake(owner, self.config.scope_concurrency);
+        Some((owner, id, task))
+    }
+
+    /// Waits for the next task, and marks it running.
+    async fn pop(&self) -> (usize, usize, Task) {
+        loop {
+            let notified = self.available.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(task) = self.take() {
+                return task;
+            }
+            notified.await;
+        }
+    }
+
+    fn finish(&self, owner: usize, id: usize) {
+        self.tasks.remove(&id);
+        let mut lanes = self.lanes.lock().unwrap();
+        if let Some(lane) = lanes.lanes.get_mut(&owner) {
+            lane.running -= 1;
+            if lane.running == 0 && lane.heap.is_empty() {
+                lanes.lanes.remove(&owner);
+            } else {
+                lanes.wake(owner, self.config.scope_concurrency);
+            }
+        }
+        drop(lanes);
+        self.available.notify_one();
+    }
+
+    /// Drops the pending tasks of a scope.
+    fn clear(&self, owner: usize) {
+        let mut lanes = self.lanes.lock().unwrap();
+        let Some(lane) = lanes.lanes.get_mut(&owner) else {
+            return;
+        };
+        let pending = mem::take(&mut lane.heap);
+        if lane.running == 0 {
+            lanes.lanes.remove(&owner);
+        }
+        drop(lanes);
+        for Queued { id, .. } in pending {
+            if let Some((_, tracked)) = self.tasks.remove(&id) {
+                tracked.abort.abort();
+            }
+        }
+    }
+}
+
+/// The workers shared by every scope of a [`Cortex`](crate::context::Cortex), see [`PoolConfig`].
+///
+/// The pending task with the highest priority is picked first, the scopes whose next tasks share the same priority
+/// are served in turn, and each of them runs at most [`PoolConfig::scope_concurrency`] tasks at the same time.
+pub struct WorkerPool {
+    queue: Arc<Queue>,
     workers: Vec<Arc<Worker>>,
-    notifier: Pin<Arc<Notify>>,
 }
 
-unsafe impl Sync for Tasker {}
+impl WorkerPool {
+    pub(crate) fn new(config: PoolConfig) -> Arc<Self> {
+        let workers = config.workers;
+        let queue = Arc::new(Queue {
+            config,
+            lanes: Default::default(),
+            available: Notify::new(),
+            counter: AtomicUsize::new(0),
+            tasks: DashMap::new(),
+        });
+        let workers = iter::repeat(()).take(workers)
+            .map(|_| Worker::new(queue.clone()).started())
+            .collect();
+        Arc::new(Self { queue, workers })
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.queue.config
+    }
+
+    /// Lists the pending and running tasks of every scope, in the order they are scheduled.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        let mut tasks: Vec<_> = self.queue.tasks.iter().map(|tracked| tracked.info.clone()).collect();
+        tasks.sort_by_key(|info| info.id);
+        tasks
+    }
+}
+
+/// The handle of a scope to the shared [`WorkerPool`].
+pub struct Tasker {
+    owner: usize,
+    pool: Arc<WorkerPool>,
+}
 
 impl Tasker {
-    pub(crate) fn new(workers: u8) -> Self {
-        let (tx, rx) = flume::unbounded();
-        let notifier = Arc::pin(Notify::new());
-        let workers: Vec<_> = iter::repeat(()).take(workers.into())
-            .map(|_| Worker::new(rx.clone(), notifier.clone()).started())
-            .collect();
-        Self { notifier, sender: tx, workers }
+    pub(crate) fn new(owner: usize, pool: Arc<WorkerPool>) -> Self {
+        Self { owner, pool }
     }
 
-    pub fn sched(&self, task: Task) {
-        self.sender.send(task).unwrap();
+    /// Schedules a task, fails with [`CrowdError::QueueFull`] if the queue of the scope is full.
+    pub fn sched(&self, task: Task) -> Result<(), CrowdError> {
+        self.pool.queue.push(self.owner, task)
+    }
+
+    pub fn sched_many(&self, tasks: impl Iterator<Item=Task>) -> Result<(), CrowdError> {
+        tasks.map(|task| self.sched(task)).collect()
+    }
+
+    /// Lists the pending and running tasks, in the order they are scheduled.
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.pool.tasks()
+            .into_iter()
+            .filter(|info| info.scope == self.owner)
+            .collect()
+    }
+
+    /// Cancels every pending or running task named `name`, returns how many are cancelled.
+    pub fn cancel(&self, name: &str) -> usize {
+        let queue = &self.pool.queue;
+        let ids: Vec<_> = queue.tasks
+            .iter()
+            .filter(|tracked| tracked.info.scope == self.owner && &*tracked.info.name == name)
+            .map(|tracked| *tracked.key())
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| queue.tasks.remove(&id))
+            .map(|(_, tracked)| tracked.abort.abort())
+            .count()
     }
 
-    pub fn sched_many(&self, tasks: impl Iterator<Item=Task>) {
-        tasks.for_each(|task| self.sender.send(task).unwrap())
+    /// Tracks a task spawned outside of the workers, so that it is listed by [`Tasker::tasks`]
+    /// and cancelled by [`Tasker::cancel`].
+    ///
+    /// The returned future resolves to `None` if the task is aborted, and untracks the task once it is finished or dropped.
+    pub(crate) fn track<F: Future>(&self, name: Arc<str>, fut: F) -> (AbortHandle, impl Future<Output = Option<F::Output>>) {
+        let queue = self.pool.queue.clone();
+        let id = queue.counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let (abort, registration) = AbortHandle::new_pair();
+        let now = Instant::now();
+        queue.tasks.insert(id, Tracked {
+            info: TaskInfo {
+                id,
+                name,
+                scope: self.owner,
+                priority: Priority::default(),
+                status: TaskStatus::Running,
+                queued_at: now,
+                started_at: Some(now),
+            },
+            abort: abort.clone(),
+        });
+        let untrack = Untrack { queue, id };
+        (abort, async move {
+            let _untrack = untrack;
+            Abortable::new(fut, registration).await.ok()
+        })
     }
 
+    /// Drops the pending tasks, the running ones are left to finish.
     pub(crate) fn dispose(&self) {
-        self.notifier.notify_waiters();
+        self.pool.queue.clear(self.owner);
+    }
+}
+
+/// Removes a task tracked by [`Tasker::track`] once it is dropped.
+struct Untrack {
+    queue: Arc<Queue>,
+    id: usize,
+}
+
+impl Drop for Untrack {
+    fn drop(&mut self) {
+        self.queue.tasks.remove(&self.id);
     }
 }
 
@@ -42,6 +451,7 @@ pub struct Task {
     name: Arc<str>,
     fut: Box<dyn Future<Output=()> + Unpin + Send + Sync>,
     blocking: bool,
+    priority: Priority,
 }
 
 impl Task {
@@ -50,6 +460,7 @@ impl Task {
             name: name.into(),
             fut: Box::new(fut),
             blocking: false,
+            priority: Priority::default(),
         }
     }
     pub fn new_blocking(name: impl Into<Arc<str>>, fut: impl Future<Output=()> + Unpin + Send + Sync + 'static) -> Self {
@@ -57,63 +468,67 @@ impl Task {
             name: name.into(),
             fut: Box::new(fut),
             blocking: true,
+            priority: Priority::default(),
         }
     }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
 }
 
 pub struct Worker {
     once: Once,
-    receiver: flume::Receiver<Task>,
+    queue: Arc<Queue>,
     process_count: AtomicUsize,
-    notify: Pin<Arc<Notify>>,
     handler: UnsafeCell<Option<JoinHandle<()>>>,
 }
 
 unsafe impl Sync for Worker {}
 
-impl<'a> Worker {
-    fn new(receiver: flume::Receiver<Task>, notify: Pin<Arc<Notify>>) -> Self {
+impl Worker {
+    fn new(queue: Arc<Queue>) -> Self {
         Self {
             once: Once::new(),
-            receiver,
-            notify,
+            queue,
             process_count: AtomicUsize::new(0),
             handler: UnsafeCell::new(None),
         }
     }
 
-    async fn handle_task(&self, t: Task) {
+    /// Runs a task, blocking tasks are moved to their own tokio task so that they do not hold the worker.
+    ///
+    /// A running blocking task still counts against the [`PoolConfig::scope_concurrency`] of its scope.
+    async fn handle_task(&self, owner: usize, id: usize, t: Task) {
+        let queue = self.queue.clone();
+        let run = async move {
+            let result = AssertUnwindSafe(t.fut).catch_unwind().await;
+            queue.finish(owner, id);
+            if let Err(panic) = result {
+                std::panic::resume_unwind(panic);
+            }
+        };
         if t.blocking {
-            tokio::task::spawn(t.fut).await.unwrap();
+            tokio::task::spawn(run);
         } else {
-            t.fut.await
+            let _ = AssertUnwindSafe(run).catch_unwind().await;
         }
     }
 
-    async fn worker_receiver(&self) -> Result<!, ()> {
+    async fn worker_receiver(&self) {
         loop {
-            if self.receiver.is_disconnected() {
-                return Err(());
-            }
-            match self.receiver.recv_async().await {
-                Ok(t) => self.handle_task(t).await,
-                Err(_) => Err(())?
-            }
+            let (owner, id, t) = self.queue.pop().await;
+            self.handle_task(owner, id, t).await;
 
             self.process_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         }
     }
 
-    async fn worker_joiner(&self) -> Result<!, ()> {
-        self.notify.notified().await;
-        Err(())
-    }
-
-    #[allow(unreachable_code)]
     fn start(self: Arc<Self>) {
         unsafe {
             self.clone().once.call_once(move || self.handler.get().write(Some(tokio::task::spawn(async move {
-                let _ = tokio::try_join!(self.worker_receiver(), self.worker_joiner());
+                self.worker_receiver().await;
             }))))
         };
     }
@@ -131,9 +546,8 @@ impl<'a> Worker {
     }
 }
 
-
-impl Drop for Worker {
+impl Drop for WorkerPool {
     fn drop(&mut self) {
-        self.abort();
+        self.workers.iter().for_each(|worker| worker.abort());
     }
 }
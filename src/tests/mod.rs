@@ -1,37 +1,1268 @@
-use std::sync::Arc;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use async_trait::async_trait;
 use mockall::mock;
-use crate::context::Cortex;
-use crate::events::{EventMatcher, UserEvent};
+use tokio::sync::Notify;
+use crate::context::{Cortex, Overrides, ScopeReport, ScopeState};
+use crate::events::{BuiltinEvent, EventMatcher, EventNya, EventPattern, EventResult, Filter, InternalEvent, ListenerOptions, UserEvent};
+use crate::pnp::{Backoff, Hot, Inject, Pluggable, RestartPolicy};
 use crate::prelude::EventMessage;
+use crate::result::{CrowdError, Error};
+use crate::service::ServiceRef;
+use crate::tasker::{PoolConfig, Priority, TaskStatus};
 
 
+/// A plugin requiring the service `database`, counting how many times it is applied.
+#[derive(Default)]
+struct Consumer(Arc<AtomicUsize>);
+
+#[async_trait]
+impl Pluggable<()> for Consumer {
+    fn name() -> &'static str {
+        "consumer"
+    }
+
+    fn inject() -> Inject {
+        Inject::new().required("database")
+    }
+
+    async fn apply(&self, _: Arc<Cortex>) -> color_eyre::Result<()> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn hot(&self, _: ()) -> Result<Hot, ()> {
+        Ok(Hot::ToRestart)
+    }
+}
+
+/// Yields until `condition` holds, panics if it does not within a second.
+async fn eventually(condition: impl Fn() -> bool) {
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while !condition() {
+            tokio::task::yield_now().await;
+        }
+    }).await.expect("condition not met in time");
+}
+
 #[tokio::test]
 async fn test_plug() {
     let cortex = Cortex::new(Arc::new(()));
     let _ = cortex.plug(async |cortex: Arc<Cortex>| {
         println!("Hello World");
-        cortex.scope.dispose();
+        cortex.root.upgrade().unwrap().scope.dispose();
         Ok(())
     }, ());
     cortex.run().await
 }
 
-// #[tokio::test]
-// async fn test_serial() {
-//     let cortex = Cortex::new(Arc::new(()));
-//     let _ = cortex.plug(async move |cortex: Arc<Cortex>| {
-//         println!("Hello World");
-//         cortex.emit(UserEvent::new("test/serial", ())).await
-//             .unwrap();
-//         Ok(())
-//     }, ());
-//     let received = Arc::new(AtomicBool::new(false));
-//     let received_clone = received.clone();
-//     cortex.on(EventMatcher::UserEvent("test/serial"), async move |args: ()| {
-//         received_clone.store(true, Ordering::SeqCst);
-//     });
-//     if !received.load(Ordering::SeqCst) {
-//         panic!("event serial failed");
-//     }
-//     cortex.run().await;
-// }
\ No newline at end of file
+#[tokio::test]
+async fn test_serial() {
+    let cortex = Cortex::new(Arc::new(()));
+    let received = Arc::new(AtomicBool::new(false));
+    let received_clone = received.clone();
+    cortex.on(EventMatcher::UserEvent("test/serial".into()), move |_: EventMessage| {
+        let received = received_clone.clone();
+        async move {
+            received.store(true, Ordering::SeqCst);
+        }
+    }).unwrap();
+    let _ = cortex.plug(async move |cortex: Arc<Cortex>| {
+        cortex.serial_event(UserEvent::new("test/serial", ())).await
+            .unwrap();
+        cortex.root.upgrade().unwrap().scope.dispose();
+        Ok(())
+    }, ());
+    cortex.run().await;
+    if !received.load(Ordering::SeqCst) {
+        panic!("event serial failed");
+    }
+}
+
+#[tokio::test]
+async fn test_typed() {
+    struct UserJoined;
+    impl EventNya for UserJoined {
+        type Args = (usize, String);
+        type Result = Option<String>;
+    }
+
+    let cortex = Cortex::new(Arc::new(()));
+    cortex.on_typed::<UserJoined>(|(id, name)| async move {
+        Some(format!("{id}:{name}"))
+    }).unwrap();
+    let greeting = cortex.bail_typed::<UserJoined>((1, "nya".to_string())).await.unwrap();
+    assert_eq!(greeting.as_deref(), Some("1:nya"));
+
+    struct Scored;
+    impl EventNya for Scored {
+        type Args = usize;
+    }
+
+    let total = Arc::new(AtomicUsize::new(0));
+    for _ in 0..2 {
+        let total = total.clone();
+        cortex.on_typed::<Scored>(move |score| {
+            total.fetch_add(score, Ordering::SeqCst);
+            async {}
+        }).unwrap();
+    }
+    cortex.parallel_typed::<Scored>(1).await.unwrap();
+    cortex.serial_typed::<Scored>(10).await.unwrap();
+    assert_eq!(total.load(Ordering::SeqCst), 22);
+}
+
+#[tokio::test]
+async fn test_once() {
+    let cortex = Cortex::new(Arc::new(()));
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_clone = count.clone();
+    cortex.once(EventMatcher::UserEvent("test/once".into()), move |_| {
+        count_clone.fetch_add(1, Ordering::SeqCst);
+        async {}
+    }).unwrap();
+    cortex.serial_event(UserEvent::new("test/once", ())).await.unwrap();
+    cortex.serial_event(UserEvent::new("test/once", ())).await.unwrap();
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_wait_for() {
+    let cortex = Cortex::new(Arc::new(()));
+    let waiting = cortex.wait_for(EventMatcher::UserEvent("test/wait".into()), None);
+    cortex.emit(UserEvent::new("test/wait", 42usize)).await.unwrap();
+    let (name, args) = waiting.await.unwrap().unwrap_user();
+    assert_eq!(name, "test/wait");
+    assert_eq!(args.downcast_ref::<usize>(), Some(&42));
+
+    let timeout = cortex.wait_for(
+        EventMatcher::UserEvent("test/never".into()),
+        Some(Duration::from_millis(10)),
+    );
+    assert!(matches!(timeout.await, Err(Error::Crowd(CrowdError::Timeout))));
+    assert!(cortex.scope.handlers.is_empty());
+
+    drop(cortex.wait_for(EventMatcher::UserEvent("test/dropped".into()), None));
+    assert!(cortex.scope.handlers.is_empty());
+}
+
+#[tokio::test]
+async fn test_order() {
+    let cortex = Cortex::new(Arc::new(()));
+    let order = Arc::new(Mutex::new(vec![]));
+    let listen = |label: &'static str, options: ListenerOptions| {
+        let order = order.clone();
+        cortex.on_with(EventMatcher::UserEvent("test/order".into()), options, move |_| {
+            order.lock().unwrap().push(label);
+            async {}
+        }).unwrap();
+    };
+    listen("first", ListenerOptions::default());
+    listen("second", ListenerOptions::default());
+    listen("prepended", ListenerOptions::default().prepend());
+    listen("prioritized", ListenerOptions::default().priority(1));
+    cortex.serial_event(UserEvent::new("test/order", ())).await.unwrap();
+    assert_eq!(*order.lock().unwrap(), ["prioritized", "prepended", "first", "second"]);
+}
+
+#[tokio::test]
+async fn test_dispatch() {
+    let cortex = Cortex::new(Arc::new(()));
+    let child = cortex.extend(Overrides::new()).unwrap();
+
+    // `emit` returns without waiting for the handlers, which are found in every scope
+    let (release, finished) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    child.on(EventMatcher::UserEvent("test/emit".into()), {
+        let (release, finished) = (release.clone(), finished.clone());
+        move |_| {
+            let (release, finished) = (release.clone(), finished.clone());
+            async move {
+                release.notified().await;
+                finished.notify_one();
+            }
+        }
+    }).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), cortex.emit(UserEvent::new("test/emit", ()))).await
+        .unwrap()
+        .unwrap();
+    release.notify_one();
+    tokio::time::timeout(Duration::from_secs(1), finished.notified()).await.unwrap();
+
+    // each handler waits for the other one, so `parallel` only returns if they run concurrently
+    let (first, second) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    for (started, other) in [(first.clone(), second.clone()), (second, first)] {
+        cortex.on(EventMatcher::UserEvent("test/parallel".into()), move |_| {
+            let (started, other) = (started.clone(), other.clone());
+            async move {
+                started.notify_one();
+                other.notified().await;
+            }
+        }).unwrap();
+    }
+    tokio::time::timeout(Duration::from_secs(1), cortex.parallel(UserEvent::new("test/parallel", ()))).await
+        .unwrap()
+        .unwrap();
+
+    let order = Arc::new(Mutex::new(vec![]));
+    for label in ["first", "second"] {
+        let order = order.clone();
+        cortex.on(EventMatcher::UserEvent("test/serial".into()), move |_| {
+            let order = order.clone();
+            async move {
+                order.lock().unwrap().push((label, "start"));
+                tokio::task::yield_now().await;
+                order.lock().unwrap().push((label, "end"));
+            }
+        }).unwrap();
+    }
+    cortex.serial_event(UserEvent::new("test/serial", ())).await.unwrap();
+    assert_eq!(*order.lock().unwrap(), [
+        ("first", "start"),
+        ("first", "end"),
+        ("second", "start"),
+        ("second", "end"),
+    ]);
+
+    let skipped = Arc::new(AtomicUsize::new(0));
+    cortex.on(EventMatcher::UserEvent("test/bail".into()), |_| async { None::<String> }).unwrap();
+    cortex.on(EventMatcher::UserEvent("test/bail".into()), |_| async { Some("second".to_string()) }).unwrap();
+    cortex.on(EventMatcher::UserEvent("test/bail".into()), {
+        let skipped = skipped.clone();
+        move |_| {
+            skipped.fetch_add(1, Ordering::SeqCst);
+            async { Some("third".to_string()) }
+        }
+    }).unwrap();
+    let result = cortex.bail(UserEvent::new("test/bail", ())).await.unwrap();
+    assert_eq!(Option::<String>::from_result(result).as_deref(), Some("second"));
+    assert_eq!(skipped.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn test_pattern() {
+    let chat = EventPattern::new("chat/*");
+    assert!(chat.matches("chat/message"));
+    assert!(!chat.matches("chat"));
+    assert!(!chat.matches("chat/message/edit"));
+
+    let all = EventPattern::new("**");
+    assert!(all.matches("chat/message"));
+    assert!(all.matches("ready"));
+
+    let nested = EventPattern::new("chat/**/edit");
+    assert!(nested.matches("chat/edit"));
+    assert!(nested.matches("chat/message/edit"));
+    assert!(!nested.matches("chat/message"));
+}
+
+#[tokio::test]
+async fn test_lifecycle_events() {
+    let cortex = Cortex::new(Arc::new(()));
+    let states = Arc::new(Mutex::new(vec![]));
+    let states_clone = states.clone();
+    cortex.on(EventMatcher::BuiltinEvent("internal/state".into()), move |evt| {
+        if let BuiltinEvent::Internal(InternalEvent::State(_, state)) = evt.unwrap_builtin() {
+            states_clone.lock().unwrap().push(state);
+        }
+        async {}
+    }).unwrap();
+    let ready = cortex.wait_for(EventMatcher::BuiltinEvent("ready".into()), Some(Duration::from_secs(1)));
+    let scope = cortex.plug(async |_: Arc<Cortex>| Ok(()), ()).unwrap();
+    let BuiltinEvent::Ready(ready) = ready.await.unwrap().unwrap_builtin() else {
+        panic!("expect a ready event");
+    };
+    assert!(Arc::ptr_eq(&ready, &scope));
+    let disposed = cortex.wait_for(EventMatcher::BuiltinEvent("dispose".into()), Some(Duration::from_secs(1)));
+    scope.dispose();
+    let BuiltinEvent::Dispose(disposed) = disposed.await.unwrap().unwrap_builtin() else {
+        panic!("expect a dispose event");
+    };
+    assert!(Arc::ptr_eq(&disposed, &scope));
+    tokio::task::yield_now().await;
+    assert_eq!(*states.lock().unwrap(), [ScopeState::Active, ScopeState::Disposed]);
+
+    // the listeners of the disposed scope itself are called
+    let (listening, disposed) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    let scope = cortex.plug({
+        let (listening, disposed) = (listening.clone(), disposed.clone());
+        move |cortex: Arc<Cortex>| {
+            let (listening, disposed) = (listening.clone(), disposed.clone());
+            async move {
+                let own = cortex.scope.clone();
+                cortex.on(EventMatcher::BuiltinEvent("dispose".into()), move |evt| {
+                    if let BuiltinEvent::Dispose(scope) = evt.unwrap_builtin() {
+                        if Arc::ptr_eq(&scope, &own) {
+                            disposed.notify_one();
+                        }
+                    }
+                    async {}
+                })?;
+                listening.notify_one();
+                Ok(())
+            }
+        }
+    }, ()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), listening.notified()).await.unwrap();
+    scope.dispose();
+    tokio::time::timeout(Duration::from_secs(1), disposed.notified()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_internal_order() {
+    let cortex = Cortex::new(Arc::new(()));
+    let states = Arc::new(Mutex::new(vec![]));
+    let failed = Arc::new(Notify::new());
+    cortex.on(EventMatcher::BuiltinEvent("internal/state".into()), {
+        let (states, failed) = (states.clone(), failed.clone());
+        move |evt| {
+            let (states, failed) = (states.clone(), failed.clone());
+            async move {
+                let BuiltinEvent::Internal(InternalEvent::State(_, state)) = evt.unwrap_builtin() else {
+                    return;
+                };
+                // a slow handler of an event holds back the following events
+                if state == ScopeState::Active {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                states.lock().unwrap().push(state);
+                if state == ScopeState::Failed {
+                    failed.notify_one();
+                }
+            }
+        }
+    }).unwrap();
+    cortex.plug(async |_: Arc<Cortex>| Err(color_eyre::eyre::eyre!("no token")), ()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), failed.notified()).await.unwrap();
+    assert_eq!(*states.lock().unwrap(), [ScopeState::Active, ScopeState::Failed]);
+}
+
+#[tokio::test]
+async fn test_effect() {
+    let cortex = Cortex::new(Arc::new(()));
+    let order = Arc::new(Mutex::new(vec![]));
+    let first = order.clone();
+    cortex.effect(move || move || first.lock().unwrap().push("first")).unwrap();
+    let second = order.clone();
+    cortex.collect("second", move || second.lock().unwrap().push("second")).unwrap();
+    let early = order.clone();
+    let handle = cortex.collect("early", move || early.lock().unwrap().push("early")).unwrap();
+    assert!(handle.dispose());
+    assert!(!handle.dispose());
+    cortex.scope.dispose();
+    assert_eq!(*order.lock().unwrap(), ["early", "second", "first"]);
+    assert!(matches!(cortex.collect("late", || {}), Err(Error::Crowd(CrowdError::InactiveScope))));
+}
+
+#[tokio::test]
+async fn test_async_dispose() {
+    let cortex = Cortex::new(Arc::new(()));
+    let flushed = Arc::new(AtomicBool::new(false));
+    let flushed_clone = flushed.clone();
+    cortex.collect_async("flush", move || async move {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        flushed_clone.store(true, Ordering::SeqCst);
+        Ok(())
+    }).unwrap();
+    cortex.collect_async("stuck", futures::future::pending).unwrap();
+    cortex.scope.set_dispose_timeout(Duration::from_millis(20));
+    cortex.scope.dispose_async().await;
+    assert!(flushed.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_dispose_with_async_cleanup() {
+    let cortex = Cortex::new(Arc::new(()));
+    let child = cortex.extend(Overrides::new()).unwrap();
+    let ticks = Arc::new(AtomicUsize::new(0));
+    child.set_interval(Duration::from_millis(5), {
+        let ticks = ticks.clone();
+        move || {
+            ticks.fetch_add(1, Ordering::SeqCst);
+        }
+    }).unwrap();
+    // disposed first, the pending cleanup must not delay the synchronous ones
+    let release = Arc::new(Notify::new());
+    child.collect_async("pending", {
+        let release = release.clone();
+        move || async move {
+            release.notified().await;
+            Ok(())
+        }
+    }).unwrap();
+    eventually(|| ticks.load(Ordering::SeqCst) > 0).await;
+    child.scope.dispose();
+    let count = ticks.load(Ordering::SeqCst);
+    // the timers fire in the order of their deadlines, so another tick would have arrived before this one
+    let elapsed = Arc::new(Notify::new());
+    cortex.set_timeout(Duration::from_millis(20), {
+        let elapsed = elapsed.clone();
+        move || elapsed.notify_one()
+    }).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), elapsed.notified()).await.unwrap();
+    assert_eq!(ticks.load(Ordering::SeqCst), count);
+    release.notify_one();
+}
+
+#[tokio::test]
+async fn test_cleanup_report() {
+    let cortex = Cortex::new(Arc::new(()));
+    let child = cortex.extend(Overrides::new()).unwrap();
+    let order = Arc::new(Mutex::new(vec![]));
+    child.collect("first", {
+        let order = order.clone();
+        move || order.lock().unwrap().push("first")
+    }).unwrap();
+    child.collect_async("second", {
+        let order = order.clone();
+        move || async move {
+            order.lock().unwrap().push("second");
+            Err(color_eyre::eyre::eyre!("flush failed"))
+        }
+    }).unwrap();
+    child.collect("third", {
+        let order = order.clone();
+        move || order.lock().unwrap().push("third")
+    }).unwrap();
+    let reported = cortex.wait_for(EventMatcher::BuiltinEvent("internal/error".into()), Some(Duration::from_secs(1)));
+    child.scope.dispose();
+    // the synchronous cleanups run on dispose, the asynchronous one is awaited in the background
+    assert_eq!(*order.lock().unwrap(), ["third", "first"]);
+    let BuiltinEvent::Internal(InternalEvent::Error(problem)) = reported.await.unwrap().unwrap_builtin() else {
+        panic!("expect an internal/error event");
+    };
+    assert_eq!(*order.lock().unwrap(), ["third", "first", "second"]);
+    assert_eq!(problem, "failed to dispose `second`: flush failed");
+}
+
+#[tokio::test]
+async fn test_service() {
+    struct Database(&'static str);
+
+    let cortex = Cortex::new(Arc::new(()));
+    cortex.provide::<Database>("database").unwrap();
+    assert!(matches!(
+        cortex.set_service("database", Arc::new(0usize)),
+        Err(Error::Crowd(CrowdError::ServiceType))
+    ));
+    let handle = cortex.set_service("database", Arc::new(Database("sqlite"))).unwrap();
+    assert_eq!(cortex.get_service::<Database>("database").unwrap().0, "sqlite");
+    assert!(cortex.get_service::<usize>("database").is_none());
+    handle.dispose();
+    assert!(cortex.get_service::<Database>("database").is_none());
+
+    cortex.set_service("database", Arc::new(Database("postgres"))).unwrap();
+    cortex.scope.dispose();
+    assert!(cortex.get_service::<Database>("database").is_none());
+}
+
+#[tokio::test]
+async fn test_inject() {
+    let cortex = Cortex::new(Arc::new(()));
+    let applied = Arc::new(AtomicUsize::new(0));
+    let scope = cortex.plug(Consumer(applied.clone()), ()).unwrap();
+    assert!(!scope.ready());
+    assert_eq!(scope.state(), ScopeState::Pending);
+    assert!(scope.tasks().is_empty());
+
+    let handle = cortex.set_service("database", Arc::new(())).unwrap();
+    eventually(|| applied.load(Ordering::SeqCst) == 1).await;
+
+    cortex.set_service("database", Arc::new(())).unwrap();
+    eventually(|| applied.load(Ordering::SeqCst) == 2).await;
+
+    handle.dispose();
+    cortex.scope.dispose();
+    assert!(!scope.ready());
+    assert_eq!(scope.state(), ScopeState::Disposed);
+    assert_eq!(applied.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_provider_plugin() {
+    let cortex = Cortex::new(Arc::new(()));
+    let applied = Arc::new(AtomicUsize::new(0));
+    let consumer = cortex.plug(Consumer(applied.clone()), ()).unwrap();
+    let provider = cortex.plug(async |cortex: Arc<Cortex>| {
+        cortex.set_service("database", Arc::new(()))?;
+        Ok(())
+    }, ()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), consumer.wait_until(ScopeState::Active)).await
+        .unwrap()
+        .unwrap();
+    // the service is provided by the fork of the plugin, rather than the scope it is applied through
+    assert!(Arc::ptr_eq(&cortex.registry.services.provider("database").unwrap(), &provider));
+
+    let report = cortex.shutdown().await;
+    let names: Vec<_> = report.scopes.iter().map(|scope| &*scope.name).collect();
+    assert_eq!(names, ["consumer", "anonymous", "root"]);
+    assert!(cortex.get_service::<()>("database").is_none());
+}
+
+#[tokio::test]
+async fn test_restart_apply() {
+    let cortex = Cortex::with_pool(Arc::new(()), PoolConfig::new().scope_concurrency(2));
+    let running = Arc::new(AtomicUsize::new(0));
+    let started = Arc::new(Notify::new());
+    let scope = cortex.plug({
+        let (running, started) = (running.clone(), started.clone());
+        move |_: Arc<Cortex>| {
+            let (running, started) = (running.clone(), started.clone());
+            async move {
+                struct Guard(Arc<AtomicUsize>);
+                impl Drop for Guard {
+                    fn drop(&mut self) {
+                        self.0.fetch_sub(1, Ordering::SeqCst);
+                    }
+                }
+                running.fetch_add(1, Ordering::SeqCst);
+                let _guard = Guard(running);
+                started.notify_one();
+                std::future::pending::<()>().await;
+                Ok(())
+            }
+        }
+    }, ()).unwrap();
+    started.notified().await;
+    scope.restart();
+    tokio::time::timeout(Duration::from_secs(1), started.notified()).await.unwrap();
+    assert_eq!(running.load(Ordering::SeqCst), 1);
+    assert_eq!(scope.tasks().len(), 1);
+}
+
+#[tokio::test]
+async fn test_service_ref() {
+    let cortex = Cortex::new(Arc::new(()));
+    let database = cortex.service_ref::<&'static str>("database");
+    assert!(database.get().is_none());
+    cortex.set_service("database", Arc::new("sqlite")).unwrap();
+    assert_eq!(*database.get().unwrap(), "sqlite");
+
+    let changed = cortex.wait_for(EventMatcher::BuiltinEvent("internal/service".into()), None);
+    cortex.set_service("database", Arc::new("postgres")).unwrap();
+    assert_eq!(*database.get().unwrap(), "postgres");
+    let BuiltinEvent::Internal(InternalEvent::Service(change)) = changed.await.unwrap().unwrap_builtin() else {
+        panic!("expect an internal/service event");
+    };
+    assert_eq!(&*change.name, "database");
+    assert_eq!(change.old.unwrap().downcast_ref::<&str>(), Some(&"sqlite"));
+    assert_eq!(change.new.unwrap().downcast_ref::<&str>(), Some(&"postgres"));
+}
+
+#[tokio::test]
+async fn test_hot_swap() {
+    struct Client(Arc<Mutex<Option<ServiceRef<&'static str>>>>);
+
+    #[async_trait]
+    impl Pluggable<()> for Client {
+        fn name() -> &'static str {
+            "client"
+        }
+
+        fn inject() -> Inject {
+            Inject::new().required("database")
+        }
+
+        async fn apply(&self, cortex: Arc<Cortex>) -> color_eyre::Result<()> {
+            *self.0.lock().unwrap() = Some(cortex.service_ref("database"));
+            Ok(())
+        }
+
+        async fn hot(&self, _: ()) -> Result<Hot, ()> {
+            Ok(Hot::ToRestart)
+        }
+    }
+
+    let cortex = Cortex::new(Arc::new(()));
+    let database = Arc::new(Mutex::new(None));
+    let scope = cortex.plug(Client(database.clone()), ()).unwrap();
+    cortex.set_service("database", Arc::new("sqlite")).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while database.lock().unwrap().is_none() || !scope.tasks().is_empty() {
+            tokio::task::yield_now().await;
+        }
+    }).await.unwrap();
+
+    let handle = cortex.set_service("database", Arc::new("postgres")).unwrap();
+    assert!(scope.tasks().is_empty());
+    assert_eq!(*database.lock().unwrap().as_ref().unwrap().get().unwrap(), "postgres");
+
+    handle.dispose();
+    assert_eq!(scope.state(), ScopeState::Pending);
+}
+
+#[tokio::test]
+async fn test_isolate() {
+    let cortex = Cortex::new(Arc::new(()));
+    cortex.set_service("database", Arc::new("shared")).unwrap();
+    cortex.set_service("cache", Arc::new("shared")).unwrap();
+    let tenant = cortex.isolate("database");
+    assert!(tenant.get_service::<&str>("database").is_none());
+    tenant.set_service("database", Arc::new("tenant")).unwrap();
+    assert_eq!(*tenant.get_service::<&str>("database").unwrap(), "tenant");
+    assert_eq!(*tenant.get_service::<&str>("cache").unwrap(), "shared");
+    assert_eq!(*cortex.get_service::<&str>("database").unwrap(), "shared");
+}
+
+#[tokio::test]
+async fn test_tenants() {
+    let cortex = Cortex::new(Arc::new(()));
+    let applied = Arc::new(AtomicUsize::new(0));
+    let first = cortex.isolate("database");
+    let second = cortex.isolate("database");
+    let a = first.plug(Consumer(applied.clone()), ()).unwrap();
+    let b = second.plug(Consumer(applied.clone()), ()).unwrap();
+    assert!(Arc::ptr_eq(a.runtime(), b.runtime()));
+    assert_eq!(a.runtime().children().len(), 2);
+
+    first.set_service("database", Arc::new(())).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), a.wait_until(ScopeState::Active)).await
+        .unwrap()
+        .unwrap();
+    assert!(!b.ready());
+    assert_eq!(b.state(), ScopeState::Pending);
+
+    a.dispose();
+    assert_eq!(b.runtime().children().len(), 1);
+    assert!(cortex.registry.get(b.runtime().plugin().unwrap()).is_some());
+}
+
+#[tokio::test]
+async fn test_extend() {
+    let cortex = Cortex::new(Arc::new(()));
+    let child = cortex.extend(Overrides::new().isolate("database")).unwrap();
+    child.set_service("database", Arc::new("tenant")).unwrap();
+    assert!(cortex.get_service::<&str>("database").is_none());
+
+    let fork = child.plug(async |_: Arc<Cortex>| Ok(()), ()).unwrap();
+    assert_eq!(fork.runtime().children().len(), 1);
+    cortex.scope.dispose();
+    assert!(fork.runtime().children().is_empty());
+    assert!(child.get_service::<&str>("database").is_none());
+    assert!(matches!(child.extend(Overrides::new()), Err(Error::Crowd(CrowdError::InactiveScope))));
+}
+
+#[tokio::test]
+async fn test_dropped_context() {
+    let cortex = Cortex::new(Arc::new(()));
+    let scope = cortex.isolate("database").plug(Consumer::default(), ()).unwrap();
+    let child = cortex.extend(Overrides::new()).unwrap();
+    let extended = child.scope.clone();
+    drop(child);
+    cortex.set_service("database", Arc::new(())).unwrap();
+    assert!(!scope.ready());
+    assert_eq!(extended.state(), ScopeState::Active);
+    cortex.scope.dispose();
+    assert_eq!(extended.state(), ScopeState::Disposed);
+    assert_eq!(scope.state(), ScopeState::Disposed);
+
+    // the forks keep working once the root context is dropped
+    let cortex = Cortex::new(Arc::new(()));
+    let child = cortex.extend(Overrides::new()).unwrap();
+    let applied = Arc::new(AtomicUsize::new(0));
+    let scope = child.plug(Consumer(applied.clone()), ()).unwrap();
+    drop(cortex);
+    child.set_service("database", Arc::new(())).unwrap();
+    eventually(|| applied.load(Ordering::SeqCst) == 1).await;
+    assert!(child.extend(Overrides::new()).is_err());
+    child.scope.dispose();
+    assert_eq!(scope.state(), ScopeState::Disposed);
+}
+
+#[tokio::test]
+async fn test_filter() {
+    fn channel(name: &'static str) -> Filter {
+        Filter::new(move |evt| match evt {
+            EventMessage::User(evt) => evt.args().downcast_ref::<&str>() == Some(&name),
+            _ => false,
+        })
+    }
+
+    let cortex = Cortex::new(Arc::new(()));
+    let received = Arc::new(Mutex::new(vec![]));
+    let listen = |ctx: Arc<Cortex>, label: &'static str| {
+        let received = received.clone();
+        ctx.on(EventMatcher::UserEvent("message".into()), move |_| {
+            received.lock().unwrap().push(label);
+            async {}
+        }).unwrap();
+    };
+    listen(cortex.filtered(channel("discord")), "discord");
+    listen(cortex.filtered(channel("discord").union(&channel("qq"))), "union");
+    listen(cortex.filtered(Filter::all().exclude(&channel("discord"))), "exclude");
+    let child = cortex.extend(Overrides::new().filter(channel("qq"))).unwrap();
+    listen(child.filtered(channel("discord")), "intersect");
+
+    cortex.serial_event(UserEvent::new("message", "discord")).await.unwrap();
+    assert_eq!(*received.lock().unwrap(), ["discord", "union"]);
+    received.lock().unwrap().clear();
+    cortex.serial_event(UserEvent::new("message", "qq")).await.unwrap();
+    assert_eq!(*received.lock().unwrap(), ["union", "exclude"]);
+}
+
+#[tokio::test]
+async fn test_run() {
+    let cortex = Cortex::new(Arc::new(()));
+    let scope = cortex.scope.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        scope.dispose();
+    });
+    tokio::time::timeout(Duration::from_secs(1), cortex.run()).await
+        .expect("`Cortex::run` should return once the root scope is disposed");
+}
+
+#[tokio::test]
+async fn test_shutdown() {
+    let cortex = Cortex::new(Arc::new(()));
+    let provider = cortex.extend(Overrides::new()).unwrap();
+    provider.set_service("database", Arc::new(())).unwrap();
+    provider.collect("broken", || panic!("broken cleanup")).unwrap();
+    let consumer = cortex.plug(Consumer::default(), ()).unwrap();
+
+    let report = cortex.shutdown().await;
+    let names: Vec<_> = report.scopes.iter().map(|scope| &*scope.name).collect();
+    assert_eq!(names, ["consumer", "extend", "root"]);
+    assert!(report.scopes[0].is_clean());
+    assert!(!report.scopes[1].is_clean());
+    assert!(report.scopes[2].is_clean());
+    assert!(!report.is_clean());
+    assert!(consumer.runtime().children().is_empty());
+    tokio::time::timeout(Duration::from_secs(1), cortex.clone().run()).await.unwrap();
+
+    let cortex = Cortex::new(Arc::new(()));
+    let outer = cortex.extend(Overrides::new()).unwrap();
+    let inner = outer.extend(Overrides::new()).unwrap();
+    let scope = Arc::downgrade(&outer.scope);
+    inner.collect("cascade", move || {
+        if let Some(scope) = scope.upgrade() {
+            scope.dispose();
+        }
+    }).unwrap();
+    let ids = [inner.scope.id(), outer.scope.id(), cortex.scope.id()];
+    let report = cortex.shutdown().await;
+    let reported: Vec<_> = report.scopes.iter().map(|scope| Some(scope.id)).collect();
+    // the outer scope is disposed by the cleanup of the inner one, and is still reported
+    assert_eq!(reported, ids);
+    assert!(report.is_clean());
+
+    // the plugin-level cleanups are awaited, and reported under the runtime
+    let cortex = Cortex::new(Arc::new(()));
+    let (applied, flushed) = (Arc::new(Notify::new()), Arc::new(AtomicBool::new(false)));
+    cortex.plug({
+        let (applied, flushed) = (applied.clone(), flushed.clone());
+        move |cortex: Arc<Cortex>| {
+            let (applied, flushed) = (applied.clone(), flushed.clone());
+            async move {
+                cortex.scope.runtime().collect_async("flush", move || async move {
+                    tokio::task::yield_now().await;
+                    flushed.store(true, Ordering::SeqCst);
+                    Err(color_eyre::eyre::eyre!("flush failed"))
+                });
+                applied.notify_one();
+                Ok(())
+            }
+        }
+    }, ()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), applied.notified()).await.unwrap();
+    let report = cortex.shutdown().await;
+    assert!(flushed.load(Ordering::SeqCst));
+    assert!(report.scopes.iter().all(ScopeReport::is_clean));
+    assert_eq!(report.runtimes.len(), 1);
+    assert_eq!(&*report.runtimes[0].name, "anonymous");
+    assert_eq!(report.runtimes[0].problems, ["failed to dispose `flush`: flush failed"]);
+    assert!(!report.is_clean());
+}
+
+#[tokio::test]
+async fn test_runtime_dispose() {
+    let cortex = Cortex::new(Arc::new(()));
+    let scope = cortex.plug(async |_: Arc<Cortex>| Ok(()), ()).unwrap();
+    let runtime = scope.runtime().clone();
+    assert_eq!(runtime.children().len(), 1);
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let reported = Arc::new(Notify::new());
+    for name in ["dispose", "internal/error"] {
+        let (events, reported) = (events.clone(), reported.clone());
+        cortex.on(EventMatcher::BuiltinEvent(name.into()), move |_| {
+            events.lock().unwrap().push(name);
+            if name == "internal/error" {
+                reported.notify_one();
+            }
+            async {}
+        }).unwrap();
+    }
+    let unloaded = Arc::new(AtomicBool::new(false));
+    runtime.collect_async("flush", || async { Err(color_eyre::eyre::eyre!("flush failed")) });
+    runtime.collect("unload", {
+        let unloaded = unloaded.clone();
+        move || unloaded.store(true, Ordering::SeqCst)
+    });
+    assert!(cortex.registry.get(runtime.plugin().unwrap()).is_some());
+    runtime.clone().dispose();
+
+    assert!(runtime.children().is_empty());
+    assert!(runtime.id().is_none());
+    assert!(unloaded.load(Ordering::SeqCst));
+    assert!(cortex.registry.entries.is_empty());
+    assert!(scope.assert_active().is_err());
+    tokio::time::timeout(Duration::from_secs(1), reported.notified()).await.unwrap();
+    assert_eq!(*events.lock().unwrap(), ["dispose", "internal/error"]);
+}
+
+#[tokio::test]
+async fn test_restart_policy() {
+    static APPLIED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Flaky;
+
+    #[async_trait]
+    impl Pluggable<()> for Flaky {
+        fn name() -> &'static str {
+            "flaky"
+        }
+
+        fn restart() -> RestartPolicy {
+            RestartPolicy::on_failure(2)
+                .backoff(Backoff::new(Duration::from_millis(5), Duration::from_millis(20)))
+        }
+
+        async fn apply(&self, _: Arc<Cortex>) -> color_eyre::Result<()> {
+            APPLIED.fetch_add(1, Ordering::SeqCst);
+            Err(color_eyre::eyre::eyre!("connection refused"))
+        }
+
+        async fn hot(&self, _: ()) -> Result<Hot, ()> {
+            Ok(Hot::ToRestart)
+        }
+    }
+
+    let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+    assert_eq!(backoff.delay(1), Duration::from_millis(100));
+    assert_eq!(backoff.delay(3), Duration::from_millis(400));
+    assert_eq!(backoff.delay(10), Duration::from_secs(1));
+    assert!(RestartPolicy::on_failure(3).next(false, 0).is_none());
+    assert_eq!(RestartPolicy::always().backoff(backoff).next(false, 0), Some(Duration::from_millis(100)));
+    assert_eq!(RestartPolicy::always().next(true, 12), Some(Duration::from_secs(30)));
+
+    let cortex = Cortex::new(Arc::new(()));
+    let restarts = Arc::new(Mutex::new(vec![]));
+    cortex.on(EventMatcher::BuiltinEvent("internal/restart".into()), {
+        let restarts = restarts.clone();
+        move |evt: EventMessage| {
+            if let EventMessage::Builtin(BuiltinEvent::Internal(InternalEvent::Restart(_, failures))) = evt {
+                restarts.lock().unwrap().push(failures);
+            }
+            async {}
+        }
+    }).unwrap();
+    let scope = cortex.plug(Flaky, ()).unwrap();
+    eventually(|| restarts.lock().unwrap().len() == 2 && APPLIED.load(Ordering::SeqCst) == 3).await;
+    tokio::time::timeout(Duration::from_secs(1), scope.wait_until(ScopeState::Failed)).await
+        .unwrap()
+        .unwrap();
+    assert_eq!(*restarts.lock().unwrap(), [1, 2]);
+
+    // `always` also restarts the plugin once its `apply` has succeeded
+    struct Steady(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Pluggable<()> for Steady {
+        fn name() -> &'static str {
+            "steady"
+        }
+
+        fn restart() -> RestartPolicy {
+            RestartPolicy::always().backoff(Backoff::new(Duration::from_millis(1), Duration::from_millis(1)))
+        }
+
+        async fn apply(&self, _: Arc<Cortex>) -> color_eyre::Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn hot(&self, _: ()) -> Result<Hot, ()> {
+            Ok(Hot::ToRestart)
+        }
+    }
+
+    let applied = Arc::new(AtomicUsize::new(0));
+    let scope = cortex.plug(Steady(applied.clone()), ()).unwrap();
+    eventually(|| applied.load(Ordering::SeqCst) >= 3).await;
+    assert!(scope.error().is_none());
+    scope.dispose();
+}
+
+#[tokio::test]
+async fn test_wait_state() {
+    let cortex = Cortex::new(Arc::new(()));
+    let scope = cortex.plug(async |_: Arc<Cortex>| Err(color_eyre::eyre::eyre!("no token")), ()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), scope.wait_until(ScopeState::Failed)).await
+        .unwrap()
+        .unwrap();
+    assert_eq!(scope.state(), ScopeState::Failed);
+    assert_eq!(scope.error().unwrap().to_string(), "no token");
+
+    let waiting = tokio::spawn({
+        let scope = scope.clone();
+        async move { scope.wait_until(ScopeState::Active).await }
+    });
+    let disposed = tokio::spawn({
+        let scope = scope.clone();
+        async move { scope.wait_disposed().await }
+    });
+    scope.dispose();
+    assert!(matches!(
+        waiting.await.unwrap(),
+        Err(Error::Crowd(CrowdError::InactiveScope))
+    ));
+    tokio::time::timeout(Duration::from_secs(1), disposed).await.unwrap().unwrap();
+    assert_eq!(scope.state(), ScopeState::Disposed);
+    assert!(scope.id().is_none());
+
+    let started = Arc::new(Notify::new());
+    let scope = cortex.plug({
+        let started = started.clone();
+        move |_: Arc<Cortex>| {
+            let started = started.clone();
+            async move {
+                started.notify_one();
+                std::future::pending::<()>().await;
+                Ok(())
+            }
+        }
+    }, ()).unwrap();
+    started.notified().await;
+    assert_eq!(scope.state(), ScopeState::Active);
+    assert_eq!(scope.cancel_task("apply"), 1);
+    assert_eq!(scope.state(), ScopeState::Failed);
+    assert!(matches!(scope.error().as_deref(), Some(Error::Crowd(CrowdError::Cancelled))));
+}
+
+#[tokio::test]
+async fn test_timer() {
+    let cortex = Cortex::new(Arc::new(()));
+    let fired = Arc::new(Mutex::new(vec![]));
+    let record = |label: &'static str| {
+        let fired = fired.clone();
+        move || fired.lock().unwrap().push(label)
+    };
+    cortex.set_timeout(Duration::from_millis(10), record("timeout")).unwrap();
+    cortex.set_timeout(Duration::from_millis(10), record("cancelled")).unwrap().dispose();
+    let child = cortex.extend(Overrides::new()).unwrap();
+    child.set_interval(Duration::from_millis(10), record("interval")).unwrap();
+    child.set_timeout(Duration::from_millis(50), record("disposed")).unwrap();
+    let sleeping = tokio::spawn({
+        let child = child.clone();
+        async move { child.sleep(Duration::from_secs(1)).await }
+    });
+    let count = |label: &'static str| fired.lock().unwrap().iter().filter(|fired| **fired == label).count();
+    eventually(|| count("interval") >= 2).await;
+    child.scope.dispose();
+    assert!(sleeping.await.unwrap().is_err());
+    let intervals = count("interval");
+    // the timers fire in the order of their deadlines, so the cancelled ones would have fired before this one
+    let elapsed = Arc::new(Notify::new());
+    cortex.set_timeout(Duration::from_millis(60), {
+        let elapsed = elapsed.clone();
+        move || elapsed.notify_one()
+    }).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), elapsed.notified()).await.unwrap();
+    assert_eq!(count("timeout"), 1);
+    assert_eq!(count("interval"), intervals);
+    assert_eq!(count("cancelled") + count("disposed"), 0);
+
+    let calls = Arc::new(Mutex::new(vec![]));
+    let throttled = cortex.throttle(Duration::from_millis(20), {
+        let calls = calls.clone();
+        move |n: usize| calls.lock().unwrap().push(("throttle", n))
+    });
+    let debounced = cortex.debounce(Duration::from_millis(20), {
+        let calls = calls.clone();
+        move |n: usize| calls.lock().unwrap().push(("debounce", n))
+    });
+    for n in 1..=3 {
+        throttled.call(n);
+        debounced.call(n);
+    }
+    eventually(|| calls.lock().unwrap().len() == 3).await;
+    assert_eq!(*calls.lock().unwrap(), [("throttle", 1), ("throttle", 3), ("debounce", 3)]);
+
+    cortex.set_timeout(Duration::ZERO, || panic!("timer panic")).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), cortex.scope.wait_until(ScopeState::Failed)).await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_plugin_timer() {
+    let cortex = Cortex::new(Arc::new(()));
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let ticked = Arc::new(Notify::new());
+    let handles = Arc::new(Mutex::new(vec![]));
+    let scope = cortex.plug({
+        let (ticks, ticked, handles) = (ticks.clone(), ticked.clone(), handles.clone());
+        move |cortex: Arc<Cortex>| {
+            let (ticks, ticked, handles) = (ticks.clone(), ticked.clone(), handles.clone());
+            async move {
+                let handle = cortex.set_interval(Duration::from_millis(5), move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    ticked.notify_one();
+                })?;
+                handles.lock().unwrap().push(handle);
+                Ok(())
+            }
+        }
+    }, ()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), ticked.notified()).await.unwrap();
+    scope.dispose();
+    // the interval is bound to the fork, so it is cleaned up with the fork rather than the root scope
+    let handle = handles.lock().unwrap().pop().unwrap();
+    assert!(!handle.dispose());
+    let count = ticks.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(ticks.load(Ordering::SeqCst), count);
+    assert!(!cortex.is_cancelled());
+
+    let child = cortex.extend(Overrides::new()).unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let throttled = child.throttle(Duration::from_millis(20), {
+        let calls = calls.clone();
+        move |_: ()| {
+            calls.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    child.scope.dispose();
+    throttled.call(());
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_spawn() {
+    let cortex = Cortex::new(Arc::new(()));
+    let mut task = cortex.spawn("answer", async { 42 }).unwrap();
+    assert_eq!(task.name(), "answer");
+    assert_eq!((&mut task).await.unwrap(), 42);
+    assert!(!task.abort());
+
+    let child = cortex.extend(Overrides::new()).unwrap();
+    let aborted = child.spawn("aborted", std::future::pending::<()>()).unwrap();
+    assert!(aborted.abort());
+    assert!(matches!(aborted.await, Err(Error::Crowd(CrowdError::Cancelled))));
+    let named = child.spawn("named", std::future::pending::<()>()).unwrap();
+    let tasks = child.scope.tasks();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(&*tasks[0].name, "named");
+    assert_eq!(tasks[0].status, TaskStatus::Running);
+    assert_eq!(child.scope.cancel_task("named"), 1);
+    assert!(matches!(named.await, Err(Error::Crowd(CrowdError::Cancelled))));
+    assert!(child.scope.tasks().is_empty());
+    let disposed = child.spawn("disposed", std::future::pending::<()>()).unwrap();
+    child.scope.dispose();
+    assert!(matches!(disposed.await, Err(Error::Crowd(CrowdError::Cancelled))));
+    assert!(child.spawn("inactive", async {}).is_err());
+
+    let panicked = cortex.spawn("panicked", async { panic!("task panic") }).unwrap();
+    assert!(matches!(panicked.await, Err(Error::PnpPanic(_))));
+    tokio::time::timeout(Duration::from_secs(1), cortex.scope.wait_until(ScopeState::Failed)).await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_cancellation() {
+    let cortex = Cortex::new(Arc::new(()));
+    let watch = |started: Arc<Notify>, stopped: Arc<Notify>| {
+        move |cortex: Arc<Cortex>| {
+            let (started, stopped) = (started.clone(), stopped.clone());
+            async move {
+                started.notify_one();
+                cortex.cancelled().await;
+                stopped.notify_one();
+                Ok(())
+            }
+        }
+    };
+
+    let (started, stopped) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    let scope = cortex.plug(watch(started.clone(), stopped.clone()), ()).unwrap();
+    started.notified().await;
+    let fork_token = scope.cancellation_token().child_token();
+    scope.dispose();
+    tokio::time::timeout(Duration::from_secs(1), stopped.notified()).await.unwrap();
+    assert!(fork_token.is_cancelled());
+    assert!(!cortex.is_cancelled());
+
+    let child = cortex.extend(Overrides::new()).unwrap();
+    let (started, stopped) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    child.plug(watch(started.clone(), stopped.clone()), ()).unwrap();
+    started.notified().await;
+    child.scope.dispose();
+    tokio::time::timeout(Duration::from_secs(1), stopped.notified()).await.unwrap();
+    assert!(child.is_cancelled());
+    assert!(!cortex.is_cancelled());
+
+    let (started, dropped) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    let scope = cortex.plug({
+        let (started, dropped) = (started.clone(), dropped.clone());
+        move |_: Arc<Cortex>| {
+            let (started, dropped) = (started.clone(), dropped.clone());
+            async move {
+                struct Guard(Arc<Notify>);
+                impl Drop for Guard {
+                    fn drop(&mut self) {
+                        self.0.notify_one();
+                    }
+                }
+                let _guard = Guard(dropped);
+                started.notify_one();
+                std::future::pending::<()>().await;
+                Ok(())
+            }
+        }
+    }, ()).unwrap();
+    started.notified().await;
+    // `apply` ignores the cancellation, so it is dropped once the dispose timeout has elapsed
+    scope.set_dispose_timeout(Duration::from_millis(10));
+    scope.dispose();
+    tokio::time::timeout(Duration::from_secs(1), dropped.notified()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_tasker() {
+    let cortex = Cortex::new(Arc::new(()));
+    let scope = cortex.scope.clone();
+    let (started, release) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    let order = Arc::new(Mutex::new(vec![]));
+    scope.ensure_task("blocker", Priority::Normal, {
+        let (started, release) = (started.clone(), release.clone());
+        move || AssertUnwindSafe(async move {
+            started.notify_one();
+            release.notified().await;
+            Ok(())
+        })
+    }).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), started.notified()).await.unwrap();
+    for (name, priority) in [("low", Priority::Low), ("normal", Priority::Normal), ("high", Priority::High)] {
+        let order = order.clone();
+        scope.ensure_task(name, priority, move || async move {
+            order.lock().unwrap().push(name);
+            Ok(())
+        }).unwrap();
+    }
+
+    let tasks = scope.tasks();
+    let names: Vec<_> = tasks.iter().map(|task| (&*task.name, task.status)).collect();
+    assert_eq!(names, [
+        ("blocker", TaskStatus::Running),
+        ("low", TaskStatus::Pending),
+        ("normal", TaskStatus::Pending),
+        ("high", TaskStatus::Pending),
+    ]);
+    assert!(tasks.iter().all(|task| Some(task.scope) == scope.id()));
+    assert!(tasks[0].started_at.is_some() && tasks[1].started_at.is_none());
+
+    assert_eq!(scope.cancel_task("normal"), 1);
+    release.notify_one();
+    eventually(|| scope.tasks().is_empty()).await;
+    assert_eq!(*order.lock().unwrap(), ["high", "low"]);
+
+    // the tasks with a higher priority are picked first, whichever scope they belong to
+    order.lock().unwrap().clear();
+    let (first, second) = (cortex.extend(Overrides::new()).unwrap(), cortex.extend(Overrides::new()).unwrap());
+    for (scope, name, priority) in [
+        (&first.scope, "first", Priority::Normal),
+        (&second.scope, "second", Priority::Normal),
+        (&scope, "lifecycle", Priority::High),
+    ] {
+        let order = order.clone();
+        scope.ensure_task(name, priority, move || async move {
+            order.lock().unwrap().push(name);
+            Ok(())
+        }).unwrap();
+    }
+    eventually(|| order.lock().unwrap().len() == 3).await;
+    assert_eq!(*order.lock().unwrap(), ["lifecycle", "first", "second"]);
+}
+
+#[tokio::test]
+async fn test_pool() {
+    let cortex = Cortex::with_pool(Arc::new(()), PoolConfig::new().workers(1).queue_bound(2));
+    let child = cortex.extend(Overrides::new()).unwrap();
+    let (started, release) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+    let order = Arc::new(Mutex::new(vec![]));
+    child.scope.ensure_task("noisy", Priority::Normal, {
+        let (started, release, order) = (started.clone(), release.clone(), order.clone());
+        move || AssertUnwindSafe(async move {
+            started.notify_one();
+            release.notified().await;
+            order.lock().unwrap().push("noisy");
+            Ok(())
+        })
+    }).unwrap();
+    let record = |name: &'static str| {
+        let order = order.clone();
+        move || async move {
+            order.lock().unwrap().push(name);
+            Ok(())
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(1), started.notified()).await.unwrap();
+    child.scope.ensure_task("queued", Priority::Normal, record("queued")).unwrap();
+    cortex.scope.ensure_task("quiet", Priority::Normal, record("quiet")).unwrap();
+    // the noisy scope runs one task at a time, and does not hold the other scopes
+    eventually(|| cortex.registry.pool.tasks().len() == 2).await;
+    assert_eq!(*order.lock().unwrap(), ["quiet"]);
+    let tasks = cortex.registry.pool.tasks();
+    let statuses: Vec<_> = tasks.iter().map(|task| (&*task.name, task.status)).collect();
+    assert_eq!(statuses, [("noisy", TaskStatus::Running), ("queued", TaskStatus::Pending)]);
+
+    // the queue is bounded per scope, a full scope does not hold the others
+    child.scope.ensure_task("second", Priority::Normal, record("second")).unwrap();
+    assert!(matches!(
+        child.scope.ensure_task("overflow", Priority::Normal, record("overflow")),
+        Err(CrowdError::QueueFull)
+    ));
+    assert!(child.scope.error().is_none());
+    cortex.scope.ensure_task("other", Priority::Normal, record("other")).unwrap();
+
+    eventually(|| order.lock().unwrap().len() == 2).await;
+
+    release.notify_one();
+    eventually(|| cortex.registry.pool.tasks().is_empty()).await;
+    assert_eq!(*order.lock().unwrap(), ["quiet", "other", "noisy", "queued", "second"]);
+}
+
+#[tokio::test]
+async fn test_long_running_apply() {
+    let cortex = Cortex::with_pool(Arc::new(()), PoolConfig::new().workers(1));
+    let started = Arc::new(AtomicUsize::new(0));
+    let serve = {
+        let started = started.clone();
+        move |cortex: Arc<Cortex>| {
+            let started = started.clone();
+            async move {
+                started.fetch_add(1, Ordering::SeqCst);
+                cortex.cancelled().await;
+                Ok(())
+            }
+        }
+    };
+    // more plugins serving until they are disposed than workers, none of them holds the others
+    let scopes: Vec<_> = (0..3).map(|_| cortex.plug(serve.clone(), ()).unwrap()).collect();
+    eventually(|| started.load(Ordering::SeqCst) == 3).await;
+    let ran = Arc::new(Notify::new());
+    cortex.scope.ensure_task("user", Priority::Normal, {
+        let ran = ran.clone();
+        move || async move {
+            ran.notify_one();
+            Ok(())
+        }
+    }).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), ran.notified()).await.unwrap();
+    for scope in scopes {
+        scope.dispose();
+    }
+}
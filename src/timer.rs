@@ -0,0 +1,171 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::time::Instant;
+use crate::context::{Cortex, DisposeHandle};
+
+type Callback<A> = Arc<dyn Fn(A) + Send + Sync>;
+
+struct ThrottleSlot<A> {
+    last: Option<Instant>,
+    pending: Option<A>,
+    timer: Option<DisposeHandle>,
+}
+
+struct ThrottleInner<A> {
+    cortex: Weak<Cortex>,
+    delay: Duration,
+    callback: Callback<A>,
+    slot: Mutex<ThrottleSlot<A>>,
+}
+
+impl<A: Send + 'static> ThrottleInner<A> {
+    /// Runs the last call made within `delay`.
+    fn flush(&self) {
+        let args = {
+            let mut slot = self.slot.lock().unwrap();
+            slot.timer = None;
+            slot.last = Some(Instant::now());
+            slot.pending.take()
+        };
+        if let Some(args) = args {
+            (self.callback)(args);
+        }
+    }
+}
+
+/// A callback called at most once every `delay`, created by [`Cortex::throttle`].
+///
+/// The first call runs immediately, and the last call made within `delay` runs once it has elapsed.
+/// Pending calls are dropped when the scope is disposed, and later calls are ignored.
+pub struct Throttled<A> {
+    inner: Arc<ThrottleInner<A>>,
+}
+
+impl<A> Clone for Throttled<A> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<A: Send + 'static> Throttled<A> {
+    pub(crate) fn new(
+        cortex: &Arc<Cortex>,
+        delay: Duration,
+        callback: impl Fn(A) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            inner: Arc::new(ThrottleInner {
+                cortex: Arc::downgrade(cortex),
+                delay,
+                callback: Arc::new(callback),
+                slot: Mutex::new(ThrottleSlot {
+                    last: None,
+                    pending: None,
+                    timer: None,
+                }),
+            }),
+        }
+    }
+
+    pub fn call(&self, args: A) {
+        let Some(cortex) = self.inner.cortex.upgrade() else {
+            return;
+        };
+        if cortex.is_cancelled() {
+            return;
+        }
+        let now = Instant::now();
+        let mut slot = self.inner.slot.lock().unwrap();
+        match slot.last {
+            Some(last) if now < last + self.inner.delay => {
+                if slot.pending.replace(args).is_none() {
+                    let inner = self.inner.clone();
+                    slot.timer = cortex
+                        .set_timeout(last + self.inner.delay - now, move || inner.flush())
+                        .ok();
+                }
+            }
+            _ => {
+                slot.last = Some(now);
+                drop(slot);
+                let callback = &self.inner.callback;
+                if std::panic::catch_unwind(AssertUnwindSafe(|| callback(args))).is_err() {
+                    cortex.scope.fail("throttle");
+                }
+            }
+        }
+    }
+
+    /// Drops the pending call, if any.
+    pub fn cancel(&self) {
+        let mut slot = self.inner.slot.lock().unwrap();
+        slot.pending = None;
+        if let Some(timer) = slot.timer.take() {
+            timer.dispose();
+        }
+    }
+}
+
+struct DebounceInner<A> {
+    cortex: Weak<Cortex>,
+    delay: Duration,
+    callback: Callback<A>,
+    timer: Mutex<Option<DisposeHandle>>,
+}
+
+/// A callback called once the calls have stopped for `delay`, with the arguments of the last call,
+/// created by [`Cortex::debounce`].
+///
+/// Pending calls are dropped when the scope is disposed.
+pub struct Debounced<A> {
+    inner: Arc<DebounceInner<A>>,
+}
+
+impl<A> Clone for Debounced<A> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<A: Send + 'static> Debounced<A> {
+    pub(crate) fn new(
+        cortex: &Arc<Cortex>,
+        delay: Duration,
+        callback: impl Fn(A) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            inner: Arc::new(DebounceInner {
+                cortex: Arc::downgrade(cortex),
+                delay,
+                callback: Arc::new(callback),
+                timer: Mutex::new(None),
+            }),
+        }
+    }
+
+    pub fn call(&self, args: A) {
+        let Some(cortex) = self.inner.cortex.upgrade() else {
+            return;
+        };
+        let mut timer = self.inner.timer.lock().unwrap();
+        if let Some(timer) = timer.take() {
+            timer.dispose();
+        }
+        let callback = self.inner.callback.clone();
+        *timer = cortex
+            .set_timeout(self.inner.delay, move || callback(args))
+            .ok();
+    }
+
+    /// Drops the pending call, if any.
+    pub fn cancel(&self) {
+        if let Some(timer) = self.inner.timer.lock().unwrap().take() {
+            timer.dispose();
+        }
+    }
+}
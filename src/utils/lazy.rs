@@ -55,39 +55,18 @@ impl<T> LazyUpdate<T> {
     }
 
     pub(crate) fn update(&self) {
-        let mut state = self.state(Ordering::Acquire);
-        loop {
-            match state {
-                LazyState::Uninit | LazyState::Initialized => unsafe {
-                    let _guard = self.mutex.lock();
-                    if self.state(Ordering::Acquire) != LazyState::Uninit {
-                        continue;
-                    } else {
-                        match self.state.compare_exchange_weak(state as u32, UPDATING, Ordering::Relaxed, Ordering::Acquire) {
-                            Ok(_) => {}
-                            Err(v) => {
-                                state = u32_to_state(v);
-                                continue;
-                            }
-                        }
-                        let f = &mut *self.updater.get();
-                        self.value.get().write(MaybeUninit::new(f(match state {
-                            LazyState::Uninit => None,
-                            LazyState::Initialized => Some(self.value.get().read().assume_init()),
-                            LazyState::Updating => { unreachable!() }
-                        })));
-                        match self.state.compare_exchange_weak(UPDATING, UPDATING, Ordering::Relaxed, Ordering::Acquire) {
-                            Ok(_) => break,
-                            Err(_) => unreachable!()
-                        }
-                    }
-                }
-                LazyState::Updating => {
-                    let _guard = self.mutex.lock();
-                    break;
-                }
-            }
+        let _guard = self.mutex.lock();
+        let prev = self.state.swap(UPDATING, Ordering::AcqRel);
+        unsafe {
+            let f = &mut *self.updater.get();
+            let value = f(match u32_to_state(prev) {
+                LazyState::Uninit => None,
+                LazyState::Initialized => Some(self.value.get().read().assume_init()),
+                LazyState::Updating => unreachable!("`LazyUpdate::update` is not reentrant"),
+            });
+            self.value.get().write(MaybeUninit::new(value));
         }
+        self.state.store(INITIALIZED, Ordering::Release);
     }
 
     pub(crate) fn force(this: &LazyUpdate<T>) {
@@ -127,6 +106,9 @@ impl<T> LazyUpdate<T> {
     where
         T: Clone,
     {
+        if self.state(Ordering::Acquire) == LazyState::Uninit {
+            self.update();
+        }
         let _guard = self.mutex.lock();
         unsafe { (*self.value.get()).assume_init_ref() }.clone()
     }